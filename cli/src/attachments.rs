@@ -1,30 +1,178 @@
-use std::path::Path;
+use std::{
+    collections::HashSet,
+    time::{Duration, SystemTime},
+};
 
 use anyhow::{bail, Context};
-use eludris::new_database_connection;
-use tokio::fs;
+use console::Style;
+use eludris::{
+    check_user_permissions, end_progress_bar, new_database_connection, new_progress_bar,
+    new_storage_backend,
+};
+use sqlx::{Connection, MySqlConnection};
 
-pub async fn remove(id: u128) -> anyhow::Result<()> {
-    if !Path::new(&format!("/usr/eludris/files/attachments/{}", id)).exists() {
-        bail!("Could not find attachment with id {}", id);
-    }
+/// Files this recent may belong to an upload whose row has not been created yet.
+const GC_GRACE_PERIOD: Duration = Duration::from_secs(10 * 60);
 
-    let mut database = new_database_connection().await?;
+/// Remove an attachment's row along with the rows attaching it to messages.
+async fn delete_rows(id: &str, database: &mut MySqlConnection) -> anyhow::Result<()> {
+    let mut transaction = database.begin().await?;
+    sqlx::query!(
+        "
+DELETE FROM message_attachments
+WHERE attachment_id = ?
+        ",
+        id,
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Could not remove attachment from messages")?;
     sqlx::query!(
         "
 DELETE FROM files
 WHERE id = ?
 AND bucket = 'attachments'
         ",
-        id.to_string(),
+        id,
     )
-    .execute(&mut database)
+    .execute(&mut transaction)
     .await
     .context("Could not remove attachment from database")?;
+    transaction.commit().await?;
+    Ok(())
+}
+
+pub async fn remove(id: u128) -> anyhow::Result<()> {
+    check_user_permissions()?;
 
-    fs::remove_file(format!("/usr/eludris/files/attachments/{}", id))
+    let mut database = new_database_connection().await?;
+    let mut transaction = database.begin().await?;
+    let file_id = sqlx::query!(
+        "
+SELECT file_id
+FROM files
+WHERE id = ?
+AND bucket = 'attachments'
+FOR UPDATE
+        ",
+        id.to_string(),
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Could not fetch attachment from database")?
+    .map(|r| r.file_id);
+    let file_id = match file_id {
+        Some(file_id) => file_id,
+        None => bail!(
+            "{}",
+            Style::new()
+                .red()
+                .apply_to(format!("Could not find attachment with id {}", id))
+        ),
+    };
+
+    delete_rows(&id.to_string(), &mut transaction).await?;
+    transaction
+        .commit()
         .await
-        .context("Failed to remove file from filesystem")?;
+        .context("Could not remove attachment from database")?;
+
+    // Deduplicated uploads share the same stored file and Effis may be reusing it for a new
+    // attachment right now, so it is left for `gc` to remove once nothing uses it anymore.
+    println!(
+        "Removed attachment {}, run {} to remove its file {} once no other attachment uses it",
+        id,
+        Style::new().bold().apply_to("eludris attachments gc"),
+        file_id
+    );
+
+    Ok(())
+}
+
+pub async fn gc(dry_run: bool) -> anyhow::Result<()> {
+    check_user_permissions()?;
+
+    let storage = new_storage_backend()?;
+    let mut database = new_database_connection().await?;
+    let bar = new_progress_bar("Collecting attachments...");
+    let rows = sqlx::query!(
+        "
+SELECT id, file_id
+FROM files
+WHERE bucket = 'attachments'
+        "
+    )
+    .fetch_all(&mut database)
+    .await
+    .context("Could not fetch attachments from database")?;
+
+    let objects = storage
+        .list("attachments/")
+        .await
+        .context("Could not list stored attachments")?;
+    // Stored files live right under the bucket while thumbnails are named after the file they
+    // were generated from.
+    let (thumbnails, blobs): (Vec<_>, Vec<_>) = objects
+        .iter()
+        .filter_map(|object| {
+            let name = object.key.strip_prefix("attachments/")?;
+            match name.strip_prefix("thumbnails/") {
+                Some(thumbnail) => Some((true, thumbnail, object)),
+                None if !name.contains('/') => Some((false, name, object)),
+                None => None,
+            }
+        })
+        .partition(|(thumbnail, ..)| *thumbnail);
+    let blob_names: HashSet<&str> = blobs.iter().map(|(_, name, _)| *name).collect();
+    let referenced: HashSet<&str> = rows.iter().map(|r| r.file_id.as_str()).collect();
+    let now = SystemTime::now();
+    let is_recent = |modified: Option<SystemTime>| {
+        modified
+            .and_then(|modified| now.duration_since(modified).ok())
+            .is_none_or(|age| age < GC_GRACE_PERIOD)
+    };
+
+    // Rows whose stored file is gone can never be fetched again.
+    let mut dangling_rows = 0;
+    for row in rows
+        .iter()
+        .filter(|r| !blob_names.contains(r.file_id.as_str()))
+    {
+        log::info!("Attachment {} is missing file {}", row.id, row.file_id);
+        if !dry_run {
+            delete_rows(&row.id, &mut database).await?;
+        }
+        dangling_rows += 1;
+    }
+
+    // Stored files no row references take up space without ever being served.
+    let mut orphaned_files = 0;
+    for (thumbnail, name, object) in blobs.iter().chain(thumbnails.iter()) {
+        let file_id = if *thumbnail {
+            name.split('_').next().unwrap_or_default()
+        } else {
+            name
+        };
+        if referenced.contains(file_id) || is_recent(object.modified) {
+            continue;
+        }
+        log::info!("File {} is not referenced by any attachment", object.key);
+        if !dry_run {
+            storage
+                .delete(&object.key)
+                .await
+                .with_context(|| format!("Could not remove {}", object.key))?;
+        }
+        orphaned_files += 1;
+    }
 
+    let action = if dry_run { "Found" } else { "Removed" };
+    end_progress_bar(
+        bar,
+        &format!(
+            "{} {} dangling attachment rows and {} orphaned files",
+            action, dangling_rows, orphaned_files
+        ),
+    );
     Ok(())
 }
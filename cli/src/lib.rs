@@ -3,6 +3,11 @@ use std::{ffi::OsStr, path::Path, process::Stdio, time::Duration};
 use anyhow::{anyhow, bail, Context};
 use indicatif::{ProgressBar, ProgressStyle};
 use sqlx::{Connection, MySqlConnection};
+use todel::{
+    conf::StorageConf,
+    storage::{new_backend, StorageBackend},
+    Conf,
+};
 use tokio::process::Command;
 use users::{get_current_uid, get_user_by_uid};
 
@@ -71,3 +76,20 @@ pub async fn new_database_connection() -> anyhow::Result<MySqlConnection> {
         .await
         .context("Could not connect to database")
 }
+
+/// Build the storage backend Effis keeps files in.
+pub fn new_storage_backend() -> anyhow::Result<Box<dyn StorageBackend>> {
+    let conf = Conf::new("/usr/eludris/Eludris.toml")?;
+    let storage = match conf.effis.storage {
+        // Effis runs from the root of its container, where the Eludris directory's files are
+        // mounted.
+        StorageConf::Local { path } if Path::new(&path).is_relative() => StorageConf::Local {
+            path: Path::new("/usr/eludris")
+                .join(path)
+                .to_string_lossy()
+                .to_string(),
+        },
+        storage => storage,
+    };
+    new_backend(&storage).context("Could not create storage backend")
+}
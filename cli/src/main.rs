@@ -65,6 +65,12 @@ enum AttachmentSubcommand {
         /// The id of the attchment to be removed
         id: u128,
     },
+    /// Removes attachments whose file is missing and files no attachment uses
+    Gc {
+        /// Only report what would be removed
+        #[arg(long)]
+        dry_run: bool,
+    },
 }
 
 #[tokio::main]
@@ -90,6 +96,7 @@ async fn main() -> anyhow::Result<()> {
         },
         Commands::Attachments { command } => match command {
             AttachmentSubcommand::Remove { id } => attachments::remove(id).await?,
+            AttachmentSubcommand::Gc { dry_run } => attachments::gc(dry_run).await?,
         },
         Commands::Clean => clean::clean().await?,
     }
@@ -1,8 +1,8 @@
-use std::path::{Path, PathBuf};
+use std::{env, path::PathBuf};
 
 use anyhow::{bail, Context};
 use console::Style;
-use eludris::check_user_permissions;
+use eludris::{check_user_permissions, new_storage_backend};
 use tokio::fs;
 
 pub async fn add(path: PathBuf) -> anyhow::Result<()> {
@@ -16,9 +16,18 @@ pub async fn add(path: PathBuf) -> anyhow::Result<()> {
                 .apply_to(format!("Could not find file {}", path.display()))
         );
     }
-    let destination_path = Path::new("/usr/eludris/files/static")
-        .join(path.file_name().context("Could not extract file name")?);
-    if destination_path.exists() {
+    let name = path
+        .file_name()
+        .context("Could not extract file name")?
+        .to_string_lossy()
+        .to_string();
+    let key = format!("static/{}", name);
+    let storage = new_storage_backend()?;
+    if storage
+        .exists(&key)
+        .await
+        .context("Could not check for existing static file")?
+    {
         bail!(
             "{}",
             Style::new()
@@ -26,16 +35,28 @@ pub async fn add(path: PathBuf) -> anyhow::Result<()> {
                 .apply_to("A static file with the same name already exists")
         );
     }
-    fs::copy(path, destination_path)
+    // Storing a file moves it, so a copy is stored to keep the original around.
+    let copy = env::temp_dir().join(format!("eludris-static-{}-{}", std::process::id(), name));
+    fs::copy(&path, &copy)
         .await
         .context("Could not make static attachment")?;
+    if let Err(err) = storage.put(&key, &copy).await {
+        fs::remove_file(&copy).await.ok();
+        return Err(err).context("Could not make static attachment");
+    }
     Ok(())
 }
 
 pub async fn remove(name: String) -> anyhow::Result<()> {
     check_user_permissions()?;
 
-    if !Path::new(&format!("/usr/eludris/files/static/{}", name)).exists() {
+    let key = format!("static/{}", name);
+    let storage = new_storage_backend()?;
+    if !storage
+        .exists(&key)
+        .await
+        .context("Could not check for static file")?
+    {
         bail!(
             "{}",
             Style::new()
@@ -43,7 +64,8 @@ pub async fn remove(name: String) -> anyhow::Result<()> {
                 .apply_to(format!("Static file {} does not exist", name))
         );
     }
-    fs::remove_file(format!("/usr/eludris/files/static/{}", name))
+    storage
+        .delete(&key)
         .await
         .context("Could not remove static file")?;
     Ok(())
@@ -5,31 +5,36 @@ mod cors;
 mod rate_limit;
 mod routes;
 
-use std::{env, path::Path};
+use std::env;
 
 use anyhow::Context;
 
 use rocket::{
     data::{Limits, ToByteUnit},
-    tokio::sync::Mutex,
+    fairing::{self, AdHoc},
     Build, Config, Rocket,
 };
-use rocket_db_pools::{deadpool_redis::Pool, sqlx::MySqlPool, Database};
+use rocket_db_pools::{sqlx::MySqlPool, Database};
+pub use todel::http::{Cache, DB};
 use todel::{
-    ids::{generate_instance_id, IDGenerator},
+    http::{catchers, init_id_generator},
+    storage::{new_backend, StorageBackend},
     Conf,
 };
-use tokio::fs;
 
 pub const BUCKETS: [&str; 1] = ["attachments"];
 
-#[derive(Database)]
-#[database("db")]
-pub struct DB(MySqlPool);
-
-#[derive(Database)]
-#[database("cache")]
-pub struct Cache(Pool);
+async fn init_storage(rocket: Rocket<Build>) -> fairing::Result {
+    let storage = match rocket.state::<Box<dyn StorageBackend>>() {
+        Some(storage) => storage,
+        None => return Err(rocket),
+    };
+    if let Err(err) = storage.init().await {
+        log::error!("Failed to initialise file storage: {}", err);
+        return Err(rocket);
+    }
+    Ok(rocket)
+}
 
 fn rocket() -> Result<Rocket<Build>, anyhow::Error> {
     #[cfg(test)]
@@ -40,6 +45,7 @@ fn rocket() -> Result<Rocket<Build>, anyhow::Error> {
     }
 
     let conf = Conf::new_from_env()?;
+    let storage = new_backend(&conf.effis.storage)?;
 
     let config = Config::figment()
         .merge((
@@ -58,7 +64,6 @@ fn rocket() -> Result<Rocket<Build>, anyhow::Error> {
                 )
                 .limit("file", conf.effis.attachment_file_size.bytes()),
         ))
-        .merge(("temp_dir", "files"))
         .merge((
             "databases.db",
             rocket_db_pools::Config {
@@ -82,13 +87,19 @@ fn rocket() -> Result<Rocket<Build>, anyhow::Error> {
         ));
 
     Ok(rocket::custom(config)
-        .manage(Mutex::new(IDGenerator::new(generate_instance_id())))
         .manage(conf)
+        .manage(storage)
         .attach(DB::init())
         .attach(Cache::init())
+        .attach(AdHoc::try_on_ignite("ID Generator", |rocket| {
+            let worker_id = rocket.state::<Conf>().and_then(|conf| conf.effis.worker_id);
+            init_id_generator(rocket, worker_id)
+        }))
+        .attach(AdHoc::try_on_ignite("Storage", init_storage))
         .attach(cors::Cors)
         .mount("/", routes::routes())
-        .mount("/static/", routes::static_routes()))
+        .mount("/static/", routes::static_routes())
+        .register("/", catchers()))
 }
 
 #[rocket::main]
@@ -107,8 +118,6 @@ async fn main() -> Result<(), anyhow::Error> {
         .await
         .context("Failed to run migrations")?;
 
-    create_file_dirs().await?;
-
     let _ = rocket()?
         .launch()
         .await
@@ -116,22 +125,3 @@ async fn main() -> Result<(), anyhow::Error> {
 
     Ok(())
 }
-
-async fn create_file_dirs() -> Result<(), anyhow::Error> {
-    try_create_dir("files").await?;
-    try_create_dir("files/static").await?;
-    for dir in BUCKETS.iter() {
-        try_create_dir(format!("files/{dir}")).await?;
-    }
-    Ok(())
-}
-
-async fn try_create_dir(path: impl AsRef<Path>) -> Result<(), anyhow::Error> {
-    if !path.as_ref().exists() {
-        fs::create_dir(&path)
-            .await
-            .with_context(|| format!("Failed to create {} directory", path.as_ref().display()))
-    } else {
-        Ok(())
-    }
-}
@@ -1,9 +1,13 @@
 use rocket::{form::Form, serde::json::Json, State};
 use rocket_db_pools::Connection;
 use todel::{
-    http::ClientIP,
+    http::{Authenticated, ClientIP},
     ids::IDGenerator,
-    models::{ErrorResponseData, FetchResponse, File, FileData, FileUpload, ValidationError},
+    models::{
+        ErrorResponseData, FetchConditions, FetchResponse, File, FileData, FileUpload,
+        ValidationError,
+    },
+    storage::StorageBackend,
     Conf,
 };
 use tokio::sync::Mutex;
@@ -14,18 +18,20 @@ use crate::{
 };
 
 #[post("/<bucket>", data = "<upload>")]
+#[allow(clippy::too_many_arguments)]
 pub async fn upload<'a>(
     bucket: &'a str,
-    upload: Form<FileUpload<'a>>,
-    ip: ClientIP,
+    upload: Form<FileUpload>,
+    auth: Authenticated,
     mut cache: Connection<Cache>,
     mut db: Connection<DB>,
     conf: &State<Conf>,
     gen: &State<Mutex<IDGenerator>>,
+    storage: &State<Box<dyn StorageBackend>>,
 ) -> RateLimitedRouteResponse<Json<FileData>> {
-    let mut rate_limiter = RateLimiter::new("attachments", bucket, ip, conf.inner());
+    let mut rate_limiter = RateLimiter::new("attachments", bucket, auth.user.id, conf.inner());
     rate_limiter
-        .process_rate_limit(upload.file.len(), &mut cache)
+        .process_rate_limit(upload.file.length, &mut cache)
         .await?;
     if !BUCKETS.contains(&bucket) {
         return Err(rate_limiter
@@ -38,7 +44,7 @@ pub async fn upload<'a>(
             )
             .unwrap());
     }
-    if upload.file.len() == 0 {
+    if upload.file.length == 0 {
         Err(rate_limiter
             .wrap_response::<_, ()>(
                 ValidationError {
@@ -53,8 +59,10 @@ pub async fn upload<'a>(
     let file = File::create(
         upload.file,
         bucket.to_string(),
+        auth.user.id,
         gen.inner(),
         &mut db,
+        storage.inner().as_ref(),
         upload.spoiler,
     )
     .await
@@ -62,14 +70,18 @@ pub async fn upload<'a>(
     rate_limiter.wrap_response(Json(file))
 }
 
-#[get("/<bucket>/<id>")]
+#[get("/<bucket>/<id>?<size>")]
+#[allow(clippy::too_many_arguments)]
 pub async fn fetch<'a>(
     bucket: &'a str,
     id: u128,
+    size: Option<u32>,
     ip: ClientIP,
+    conditions: FetchConditions,
     mut cache: Connection<Cache>,
     mut db: Connection<DB>,
     conf: &State<Conf>,
+    storage: &State<Box<dyn StorageBackend>>,
 ) -> RateLimitedRouteResponse<FetchResponse<'a>> {
     let mut rate_limiter = RateLimiter::new("fetch_file", bucket, ip, conf.inner());
     rate_limiter.process_rate_limit(0, &mut cache).await?;
@@ -84,20 +96,30 @@ pub async fn fetch<'a>(
             )
             .unwrap());
     }
-    let file = File::fetch_file(id, bucket, &mut db)
-        .await
-        .map_err(|e| rate_limiter.wrap_response::<_, ()>(e).unwrap())?;
+    let file = File::fetch_file(
+        id,
+        bucket,
+        size,
+        &conditions,
+        &mut db,
+        storage.inner().as_ref(),
+    )
+    .await
+    .map_err(|e| rate_limiter.wrap_response::<_, ()>(e).unwrap())?;
     rate_limiter.wrap_response(file)
 }
 
 #[get("/<bucket>/<id>/download")]
+#[allow(clippy::too_many_arguments)]
 pub async fn fetch_download<'a>(
     bucket: &'a str,
     id: u128,
     ip: ClientIP,
+    conditions: FetchConditions,
     mut cache: Connection<Cache>,
     mut db: Connection<DB>,
     conf: &State<Conf>,
+    storage: &State<Box<dyn StorageBackend>>,
 ) -> RateLimitedRouteResponse<FetchResponse<'a>> {
     let mut rate_limiter = RateLimiter::new("fetch_file", bucket, ip, conf.inner());
     rate_limiter.process_rate_limit(0, &mut cache).await?;
@@ -112,9 +134,10 @@ pub async fn fetch_download<'a>(
             )
             .unwrap());
     }
-    let file = File::fetch_file_download(id, bucket, &mut db)
-        .await
-        .map_err(|e| rate_limiter.wrap_response::<_, ()>(e).unwrap())?;
+    let file =
+        File::fetch_file_download(id, bucket, &conditions, &mut db, storage.inner().as_ref())
+            .await
+            .map_err(|e| rate_limiter.wrap_response::<_, ()>(e).unwrap())?;
     rate_limiter.wrap_response(file)
 }
 
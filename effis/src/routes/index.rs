@@ -1,9 +1,13 @@
 use rocket::{form::Form, serde::json::Json, State};
 use rocket_db_pools::Connection;
 use todel::{
-    http::ClientIP,
+    http::{Authenticated, ClientIP},
     ids::IDGenerator,
-    models::{ErrorResponseData, FetchResponse, File, FileData, FileUpload, ValidationError},
+    models::{
+        ErrorResponseData, FetchConditions, FetchResponse, File, FileData, FileUpload,
+        ValidationError,
+    },
+    storage::StorageBackend,
     Conf,
 };
 use tokio::sync::Mutex;
@@ -14,19 +18,21 @@ use crate::{
 };
 
 #[post("/", data = "<upload>")]
-pub async fn upload<'a>(
-    upload: Form<FileUpload<'a>>,
-    ip: ClientIP,
+pub async fn upload(
+    upload: Form<FileUpload>,
+    auth: Authenticated,
     mut cache: Connection<Cache>,
     mut db: Connection<DB>,
     conf: &State<Conf>,
     gen: &State<Mutex<IDGenerator>>,
+    storage: &State<Box<dyn StorageBackend>>,
 ) -> RateLimitedRouteResponse<Json<FileData>> {
-    let mut rate_limiter = RateLimiter::new("attachments", "attachments", ip, conf.inner());
+    let mut rate_limiter =
+        RateLimiter::new("attachments", "attachments", auth.user.id, conf.inner());
     rate_limiter
-        .process_rate_limit(upload.file.len(), &mut cache)
+        .process_rate_limit(upload.file.length, &mut cache)
         .await?;
-    if upload.file.len() == 0 {
+    if upload.file.length == 0 {
         Err(rate_limiter
             .wrap_response::<_, ()>(
                 ValidationError {
@@ -41,8 +47,10 @@ pub async fn upload<'a>(
     let file = File::create(
         upload.file,
         "attachments".to_string(),
+        auth.user.id,
         gen.inner(),
         &mut db,
+        storage.inner().as_ref(),
         upload.spoiler,
     )
     .await
@@ -50,19 +58,30 @@ pub async fn upload<'a>(
     rate_limiter.wrap_response(Json(file))
 }
 
-#[get("/<id>")]
+#[get("/<id>?<size>")]
+#[allow(clippy::too_many_arguments)]
 pub async fn fetch<'a>(
     id: u128,
+    size: Option<u32>,
     ip: ClientIP,
+    conditions: FetchConditions,
     mut cache: Connection<Cache>,
     mut db: Connection<DB>,
     conf: &State<Conf>,
+    storage: &State<Box<dyn StorageBackend>>,
 ) -> RateLimitedRouteResponse<FetchResponse<'a>> {
     let mut rate_limiter = RateLimiter::new("fetch_file", "attachments", ip, conf.inner());
     rate_limiter.process_rate_limit(0, &mut cache).await?;
-    let file = File::fetch_file(id, "attachments", &mut db)
-        .await
-        .map_err(|e| rate_limiter.wrap_response::<_, ()>(e).unwrap())?;
+    let file = File::fetch_file(
+        id,
+        "attachments",
+        size,
+        &conditions,
+        &mut db,
+        storage.inner().as_ref(),
+    )
+    .await
+    .map_err(|e| rate_limiter.wrap_response::<_, ()>(e).unwrap())?;
     rate_limiter.wrap_response(file)
 }
 
@@ -70,15 +89,23 @@ pub async fn fetch<'a>(
 pub async fn fetch_download<'a>(
     id: u128,
     ip: ClientIP,
+    conditions: FetchConditions,
     mut cache: Connection<Cache>,
     mut db: Connection<DB>,
     conf: &State<Conf>,
+    storage: &State<Box<dyn StorageBackend>>,
 ) -> RateLimitedRouteResponse<FetchResponse<'a>> {
     let mut rate_limiter = RateLimiter::new("fetch_file", "attachments", ip, conf.inner());
     rate_limiter.process_rate_limit(0, &mut cache).await?;
-    let file = File::fetch_file_download(id, "attachments", &mut db)
-        .await
-        .map_err(|e| rate_limiter.wrap_response::<_, ()>(e).unwrap())?;
+    let file = File::fetch_file_download(
+        id,
+        "attachments",
+        &conditions,
+        &mut db,
+        storage.inner().as_ref(),
+    )
+    .await
+    .map_err(|e| rate_limiter.wrap_response::<_, ()>(e).unwrap())?;
     rate_limiter.wrap_response(file)
 }
 
@@ -12,19 +12,21 @@ use rocket_db_pools::Connection;
 use todel::{
     http::ClientIP,
     models::{
-        ErrorResponse, ErrorResponseData, FetchResponse, NotFoundError, ServerError,
-        ValidationError,
+        ErrorResponse, ErrorResponseData, FetchConditions, FetchResponse, NotFoundError,
+        ServerError, ValidationError,
     },
+    storage::StorageBackend,
     Conf,
 };
-use tokio::fs::File;
 
 #[get("/<name>", rank = 1)]
 pub async fn fetch_static_file<'a>(
     name: &'a str,
     ip: ClientIP,
+    conditions: FetchConditions,
     mut cache: Connection<Cache>,
     conf: &State<Conf>,
+    storage: &State<Box<dyn StorageBackend>>,
 ) -> RateLimitedRouteResponse<FetchResponse<'a>> {
     let mut rate_limiter = RateLimiter::new("fetch_file", "static", ip, conf.inner());
     rate_limiter.process_rate_limit(0, &mut cache).await?;
@@ -54,44 +56,49 @@ pub async fn fetch_static_file<'a>(
         })?),
         None => None,
     };
-    let file = File::open(Path::new("./files/static").join(path))
-        .await
-        .map_err(|e| {
-            if e.kind() == ErrorKind::NotFound {
-                rate_limiter
-                    .wrap_response::<_, ()>(NotFoundError.to_error_response())
-                    .unwrap()
-            } else {
-                rate_limiter
-                    .wrap_response::<_, ()>(
-                        ServerError {
-                            error: "Failed to upload file".to_string(),
-                        }
-                        .to_error_response(),
-                    )
-                    .unwrap()
-            }
-        })?;
-    log::info!("Fetched static file {}", name);
-    rate_limiter.wrap_response(FetchResponse {
-        file,
-        disposition: Header::new(
+    let file = FetchResponse::new(
+        storage.inner().as_ref(),
+        &format!("static/{}", path.display()),
+        None,
+        &conditions,
+        Header::new(
             "Content-Disposition",
             format!(
                 "inline; filename=\"{}\"",
                 path.file_name().unwrap().to_str().unwrap()
             ),
         ),
-        content_type: content_type.unwrap_or(ContentType::Any),
-    })
+        content_type.unwrap_or(ContentType::Any),
+    )
+    .await
+    .map_err(|e| {
+        if e.kind() == ErrorKind::NotFound {
+            rate_limiter
+                .wrap_response::<_, ()>(NotFoundError.to_error_response())
+                .unwrap()
+        } else {
+            rate_limiter
+                .wrap_response::<_, ()>(
+                    ServerError {
+                        error: "Failed to upload file".to_string(),
+                    }
+                    .to_error_response(),
+                )
+                .unwrap()
+        }
+    })?;
+    log::info!("Fetched static file {}", name);
+    rate_limiter.wrap_response(file)
 }
 
 #[get("/<name>/download", rank = 1)]
 pub async fn download_static_file<'a>(
     name: &'a str,
     ip: ClientIP,
+    conditions: FetchConditions,
     mut cache: Connection<Cache>,
     conf: &State<Conf>,
+    storage: &State<Box<dyn StorageBackend>>,
 ) -> RateLimitedRouteResponse<Result<FetchResponse<'a>, ErrorResponse>> {
     let mut rate_limiter = RateLimiter::new("fetch_file", "static", ip, conf.inner());
     rate_limiter.process_rate_limit(0, &mut cache).await?;
@@ -121,34 +128,37 @@ pub async fn download_static_file<'a>(
         })?),
         None => None,
     };
-    let file = File::open(Path::new("./files/static").join(path))
-        .await
-        .map_err(|e| {
-            if e.kind() == ErrorKind::NotFound {
-                rate_limiter
-                    .wrap_response::<_, ()>(NotFoundError.to_error_response())
-                    .unwrap()
-            } else {
-                rate_limiter
-                    .wrap_response::<_, ()>(
-                        ServerError {
-                            error: "Failed to upload file".to_string(),
-                        }
-                        .to_error_response(),
-                    )
-                    .unwrap()
-            }
-        })?;
-    log::info!("Fetched static file {}", name);
-    rate_limiter.wrap_response(Ok(FetchResponse {
-        file,
-        disposition: Header::new(
+    let file = FetchResponse::new(
+        storage.inner().as_ref(),
+        &format!("static/{}", path.display()),
+        None,
+        &conditions,
+        Header::new(
             "Content-Disposition",
             format!(
                 "attachment; filename=\"{}\"",
                 path.file_name().unwrap().to_str().unwrap()
             ),
         ),
-        content_type: content_type.unwrap_or(ContentType::Any),
-    }))
+        content_type.unwrap_or(ContentType::Any),
+    )
+    .await
+    .map_err(|e| {
+        if e.kind() == ErrorKind::NotFound {
+            rate_limiter
+                .wrap_response::<_, ()>(NotFoundError.to_error_response())
+                .unwrap()
+        } else {
+            rate_limiter
+                .wrap_response::<_, ()>(
+                    ServerError {
+                        error: "Failed to upload file".to_string(),
+                    }
+                    .to_error_response(),
+                )
+                .unwrap()
+        }
+    })?;
+    log::info!("Fetched static file {}", name);
+    rate_limiter.wrap_response(Ok(file))
 }
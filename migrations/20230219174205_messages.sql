@@ -0,0 +1,5 @@
+CREATE TABLE IF NOT EXISTS messages (
+  id BINARY(16) NOT NULL PRIMARY KEY,
+  author VARCHAR(32) NOT NULL,
+  content TEXT NOT NULL
+)
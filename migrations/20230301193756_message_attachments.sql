@@ -0,0 +1,7 @@
+ALTER TABLE messages ADD COLUMN IF NOT EXISTS reply_to BINARY(16);
+
+CREATE TABLE IF NOT EXISTS message_attachments (
+  message_id BINARY(16) NOT NULL,
+  attachment_id VARCHAR(40) NOT NULL,
+  PRIMARY KEY (message_id, attachment_id)
+)
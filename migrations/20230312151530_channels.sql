@@ -0,0 +1,14 @@
+CREATE TABLE IF NOT EXISTS channels (
+  id BINARY(16) NOT NULL PRIMARY KEY,
+  name VARCHAR(32) NOT NULL
+);
+
+-- Every instance starts with a "general" channel which the messages sent before
+-- channels existed are moved to.
+INSERT IGNORE INTO channels(id, name)
+VALUES(X'00000000000000000000000000000001', 'general');
+
+ALTER TABLE messages ADD COLUMN IF NOT EXISTS channel_id BINARY(16) NOT NULL DEFAULT X'00000000000000000000000000000001';
+ALTER TABLE messages ALTER COLUMN channel_id DROP DEFAULT;
+
+CREATE INDEX IF NOT EXISTS messages_channel_id ON messages (channel_id, id);
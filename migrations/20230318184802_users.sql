@@ -0,0 +1,14 @@
+CREATE TABLE IF NOT EXISTS users (
+  id BINARY(16) NOT NULL PRIMARY KEY,
+  username VARCHAR(32) NOT NULL UNIQUE,
+  password VARCHAR(255) NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS sessions (
+  id BINARY(16) NOT NULL PRIMARY KEY,
+  user_id BINARY(16) NOT NULL,
+  token VARCHAR(64) NOT NULL UNIQUE
+);
+
+-- Messages sent before users existed have no author_id.
+ALTER TABLE messages ADD COLUMN IF NOT EXISTS author_id BINARY(16);
@@ -0,0 +1,2 @@
+-- Files uploaded before users existed have no uploader_id.
+ALTER TABLE files ADD COLUMN IF NOT EXISTS uploader_id BINARY(16);
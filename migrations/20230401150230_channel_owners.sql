@@ -0,0 +1,2 @@
+-- Channels created before users existed, such as the "general" channel, have no owner_id.
+ALTER TABLE channels ADD COLUMN IF NOT EXISTS owner_id BINARY(16);
@@ -0,0 +1,2 @@
+-- Attachments stored before their order was kept all share position 0.
+ALTER TABLE message_attachments ADD COLUMN IF NOT EXISTS position INT UNSIGNED NOT NULL DEFAULT 0;
@@ -11,16 +11,30 @@ mod routes;
 use std::env;
 
 use anyhow::Context;
-use rocket::{Build, Config, Rocket};
+use rocket::{
+    fairing::{self, AdHoc},
+    Build, Config, Rocket,
+};
 use rocket_db_pools::Database;
 use routes::*;
-use todel::Conf;
+pub use todel::http::{Cache, DB};
+use todel::{
+    http::{catchers, init_id_generator},
+    Conf,
+};
 
-const VERSION: &str = env!("CARGO_PKG_VERSION");
-
-#[derive(Database)]
-#[database("cache")]
-pub struct Cache(deadpool_redis::Pool);
+async fn run_migrations(rocket: Rocket<Build>) -> fairing::Result {
+    match DB::fetch(&rocket) {
+        Some(db) => match sqlx::migrate!("../migrations").run(&**db).await {
+            Ok(()) => Ok(rocket),
+            Err(err) => {
+                log::error!("Failed to run migrations: {}", err);
+                Err(rocket)
+            }
+        },
+        None => Err(rocket),
+    }
+}
 
 fn rocket() -> Result<Rocket<Build>, anyhow::Error> {
     #[cfg(test)]
@@ -62,9 +76,21 @@ fn rocket() -> Result<Rocket<Build>, anyhow::Error> {
 
     Ok(rocket::custom(config)
         .mount("/", get_routes())
-        .mount("/messages", messages::get_routes())
+        .mount("/channels", channels::get_routes())
+        .mount("/channels", messages::get_routes())
+        .mount("/users", users::get_routes())
+        .mount("/sessions", sessions::get_routes())
+        .register("/", catchers())
         .manage(Conf::new_from_env()?)
+        .attach(DB::init())
+        .attach(AdHoc::try_on_ignite("Database Migrations", run_migrations))
         .attach(Cache::init())
+        .attach(AdHoc::try_on_ignite("ID Generator", |rocket| {
+            let worker_id = rocket
+                .state::<Conf>()
+                .and_then(|conf| conf.oprish.worker_id);
+            init_id_generator(rocket, worker_id)
+        }))
         .attach(cors::Cors))
 }
 
@@ -17,7 +17,6 @@ pub type RateLimitedRouteResponse<T> =
 
 /// The necessary headers for responses
 #[derive(Debug, Responder)]
-#[response(content_type = "json")]
 pub struct RateLimitHeaderWrapper<T> {
     pub inner: T,
     pub rate_limit_reset: Header<'static>,
@@ -47,6 +46,16 @@ impl RateLimiter {
         let rate_limit = match bucket {
             "info" => &conf.oprish.rate_limits.info,
             "message_create" => &conf.oprish.rate_limits.message_create,
+            "message_edit" => &conf.oprish.rate_limits.message_edit,
+            "message_delete" => &conf.oprish.rate_limits.message_delete,
+            "get_messages" => &conf.oprish.rate_limits.get_messages,
+            "channel_create" => &conf.oprish.rate_limits.channel_create,
+            "channel_edit" => &conf.oprish.rate_limits.channel_edit,
+            "channel_delete" => &conf.oprish.rate_limits.channel_delete,
+            "get_channels" => &conf.oprish.rate_limits.get_channels,
+            "user_create" => &conf.oprish.rate_limits.user_create,
+            "session_create" => &conf.oprish.rate_limits.session_create,
+            "typing" => &conf.oprish.rate_limits.typing,
             "rate_limits" => &conf.oprish.rate_limits.rate_limits,
             _ => unreachable!(),
         };
@@ -0,0 +1,167 @@
+use crate::rate_limit::{RateLimitedRouteResponse, RateLimiter};
+use crate::{Cache, DB};
+use rocket::http::Status;
+use rocket::tokio::sync::Mutex;
+use rocket::{Route, State};
+use rocket_db_pools::Connection;
+use todel::http::{Authenticated, ClientIP, Encoded};
+use todel::ids::{IDGenerator, Snowflake};
+use todel::models::{
+    Channel, ChannelCreate, ChannelEdit, ErrorResponse, ErrorResponseData, Payload, ValidationError,
+};
+use todel::Conf;
+
+/// Check whether a channel name is valid.
+fn validate_name(name: &str) -> Result<(), ErrorResponse> {
+    if name.is_empty() || name.len() > 32 {
+        Err(ValidationError {
+            field_name: "name".to_string(),
+            error: "Channel name has to be between 1 and 32 characters long.".to_string(),
+        }
+        .to_error_response())
+    } else {
+        Ok(())
+    }
+}
+
+#[post("/", data = "<channel>")]
+pub async fn index(
+    channel: Encoded<ChannelCreate>,
+    auth: Authenticated,
+    address: ClientIP,
+    mut cache: Connection<Cache>,
+    mut db: Connection<DB>,
+    conf: &State<Conf>,
+    gen: &State<Mutex<IDGenerator>>,
+) -> RateLimitedRouteResponse<Result<Encoded<Channel>, ErrorResponse>> {
+    let mut rate_limiter = RateLimiter::new("channel_create", address, conf.inner());
+    rate_limiter.process_rate_limit(&mut cache).await?;
+    let channel = channel.into_inner();
+    if let Err(err) = validate_name(&channel.name) {
+        return rate_limiter.wrap_response(Err(err));
+    }
+    let channel = match Channel::create(channel, &auth.user, gen.inner(), &mut db).await {
+        Ok(channel) => channel,
+        Err(err) => return rate_limiter.wrap_response(Err(err)),
+    };
+    let payload = Payload::ChannelCreate(channel);
+    payload.publish(&mut *cache).await.unwrap();
+    if let Payload::ChannelCreate(channel) = payload {
+        rate_limiter.wrap_response(Ok(Encoded(channel)))
+    } else {
+        unreachable!()
+    }
+}
+
+#[get("/")]
+pub async fn get_channels(
+    address: ClientIP,
+    mut cache: Connection<Cache>,
+    mut db: Connection<DB>,
+    conf: &State<Conf>,
+) -> RateLimitedRouteResponse<Result<Encoded<Vec<Channel>>, ErrorResponse>> {
+    let mut rate_limiter = RateLimiter::new("get_channels", address, conf.inner());
+    rate_limiter.process_rate_limit(&mut cache).await?;
+    rate_limiter.wrap_response(Channel::get_all(&mut db).await.map(Encoded))
+}
+
+#[get("/<id>")]
+pub async fn get_channel(
+    id: Snowflake,
+    address: ClientIP,
+    mut cache: Connection<Cache>,
+    mut db: Connection<DB>,
+    conf: &State<Conf>,
+) -> RateLimitedRouteResponse<Result<Encoded<Channel>, ErrorResponse>> {
+    let mut rate_limiter = RateLimiter::new("get_channels", address, conf.inner());
+    rate_limiter.process_rate_limit(&mut cache).await?;
+    rate_limiter.wrap_response(Channel::get(id.get(), &mut db).await.map(Encoded))
+}
+
+#[patch("/<id>", data = "<edit>")]
+pub async fn edit(
+    id: Snowflake,
+    edit: Encoded<ChannelEdit>,
+    auth: Authenticated,
+    address: ClientIP,
+    mut cache: Connection<Cache>,
+    mut db: Connection<DB>,
+    conf: &State<Conf>,
+) -> RateLimitedRouteResponse<Result<Encoded<Channel>, ErrorResponse>> {
+    let mut rate_limiter = RateLimiter::new("channel_edit", address, conf.inner());
+    rate_limiter.process_rate_limit(&mut cache).await?;
+    let edit = edit.into_inner();
+    let name = match &edit.name {
+        Some(name) => name,
+        None => {
+            return rate_limiter.wrap_response(Err(ValidationError {
+                field_name: "name".to_string(),
+                error: "Channel edits have to change the name.".to_string(),
+            }
+            .to_error_response()))
+        }
+    };
+    if let Err(err) = validate_name(name) {
+        return rate_limiter.wrap_response(Err(err));
+    }
+    let channel = match Channel::edit(id.get(), auth.user.id, edit, &mut db).await {
+        Ok(channel) => channel,
+        Err(err) => return rate_limiter.wrap_response(Err(err)),
+    };
+    let payload = Payload::ChannelUpdate(channel);
+    payload.publish(&mut *cache).await.unwrap();
+    if let Payload::ChannelUpdate(channel) = payload {
+        rate_limiter.wrap_response(Ok(Encoded(channel)))
+    } else {
+        unreachable!()
+    }
+}
+
+#[delete("/<id>")]
+pub async fn delete(
+    id: Snowflake,
+    auth: Authenticated,
+    address: ClientIP,
+    mut cache: Connection<Cache>,
+    mut db: Connection<DB>,
+    conf: &State<Conf>,
+) -> RateLimitedRouteResponse<Result<Status, ErrorResponse>> {
+    let mut rate_limiter = RateLimiter::new("channel_delete", address, conf.inner());
+    rate_limiter.process_rate_limit(&mut cache).await?;
+    if let Err(err) = Channel::delete(id.get(), auth.user.id, &mut db).await {
+        return rate_limiter.wrap_response(Err(err));
+    }
+    Payload::ChannelDelete { id: id.get() }
+        .publish(&mut *cache)
+        .await
+        .unwrap();
+    rate_limiter.wrap_response(Ok(Status::NoContent))
+}
+
+#[post("/<id>/typing")]
+pub async fn typing(
+    id: Snowflake,
+    auth: Authenticated,
+    address: ClientIP,
+    mut cache: Connection<Cache>,
+    mut db: Connection<DB>,
+    conf: &State<Conf>,
+) -> RateLimitedRouteResponse<Result<Status, ErrorResponse>> {
+    let mut rate_limiter = RateLimiter::new("typing", address, conf.inner());
+    rate_limiter.process_rate_limit(&mut cache).await?;
+    if let Err(err) = Channel::get(id.get(), &mut db).await {
+        return rate_limiter.wrap_response(Err(err));
+    }
+    Payload::TypingStart {
+        channel_id: id.get(),
+        user: auth.user,
+    }
+    .publish(&mut *cache)
+    .await
+    .unwrap();
+    rate_limiter.wrap_response(Ok(Status::NoContent))
+}
+
+pub fn get_routes() -> Vec<Route> {
+    routes![index, get_channels, get_channel, edit, delete, typing]
+}
@@ -1,52 +1,167 @@
 use crate::rate_limit::{RateLimitedRouteResponse, RateLimiter};
-use crate::Cache;
-use deadpool_redis::redis::AsyncCommands;
-use rocket::serde::json::Json;
+use crate::{Cache, DB};
+use rocket::http::Status;
+use rocket::tokio::sync::Mutex;
 use rocket::{Route, State};
 use rocket_db_pools::Connection;
-use todel::http::ClientIP;
-use todel::models::{ErrorResponse, ErrorResponseData, Message, Payload, ValidationError};
+use todel::http::{Authenticated, ClientIP, Encoded};
+use todel::ids::{IDGenerator, Snowflake};
+use todel::models::{
+    ErrorResponse, ErrorResponseData, Message, MessageCreate, MessageEdit, Payload, ValidationError,
+};
 use todel::Conf;
 
-#[post("/", data = "<message>")]
+/// The maximum amount of messages that can be fetched at once.
+const MESSAGE_HISTORY_LIMIT: u32 = 100;
+
+/// Check whether a message's content is valid.
+fn validate_content(content: &str, conf: &Conf) -> Result<(), ErrorResponse> {
+    if content.is_empty() || content.len() > conf.oprish.message_limit {
+        Err(ValidationError {
+            field_name: "content".to_string(),
+            error: format!(
+                "Message content has to be between 1 and {} characters long.",
+                conf.oprish.message_limit
+            ),
+        }
+        .to_error_response())
+    } else {
+        Ok(())
+    }
+}
+
+#[post("/<channel_id>/messages", data = "<message>")]
+#[allow(clippy::too_many_arguments)]
 pub async fn index(
-    message: Json<Message>,
+    channel_id: Snowflake,
+    message: Encoded<MessageCreate>,
+    auth: Authenticated,
     address: ClientIP,
     mut cache: Connection<Cache>,
+    mut db: Connection<DB>,
     conf: &State<Conf>,
-) -> RateLimitedRouteResponse<Result<Json<Message>, ErrorResponse>> {
+    gen: &State<Mutex<IDGenerator>>,
+) -> RateLimitedRouteResponse<Result<Encoded<Message>, ErrorResponse>> {
     let mut rate_limiter = RateLimiter::new("message_create", address, conf.inner());
     rate_limiter.process_rate_limit(&mut cache).await?;
     let message = message.into_inner();
-    if message.author.len() < 2 || message.author.len() > 32 {
-        rate_limiter.wrap_response(Err(ValidationError {
-            field_name: "author".to_string(),
-            error: "Message author has to be between 2 and 32 characters long.".to_string(),
+    if let Err(err) = validate_content(&message.content, conf.inner()) {
+        return rate_limiter.wrap_response(Err(err));
+    }
+    let message =
+        match Message::create(channel_id.get(), &auth.user, message, gen.inner(), &mut db).await {
+            Ok(message) => message,
+            Err(err) => return rate_limiter.wrap_response(Err(err)),
+        };
+    let payload = Payload::MessageCreate(message);
+    payload.publish(&mut *cache).await.unwrap();
+    if let Payload::MessageCreate(message) = payload {
+        rate_limiter.wrap_response(Ok(Encoded(message)))
+    } else {
+        unreachable!()
+    }
+}
+
+#[patch("/<channel_id>/messages/<id>", data = "<edit>")]
+#[allow(clippy::too_many_arguments)]
+pub async fn edit(
+    channel_id: Snowflake,
+    id: Snowflake,
+    edit: Encoded<MessageEdit>,
+    auth: Authenticated,
+    address: ClientIP,
+    mut cache: Connection<Cache>,
+    mut db: Connection<DB>,
+    conf: &State<Conf>,
+) -> RateLimitedRouteResponse<Result<Encoded<Message>, ErrorResponse>> {
+    let mut rate_limiter = RateLimiter::new("message_edit", address, conf.inner());
+    rate_limiter.process_rate_limit(&mut cache).await?;
+    let edit = edit.into_inner();
+    let content = match &edit.content {
+        Some(content) => content,
+        None => {
+            return rate_limiter.wrap_response(Err(ValidationError {
+                field_name: "content".to_string(),
+                error: "Message edits have to change the content.".to_string(),
+            }
+            .to_error_response()))
         }
-        .to_error_response()))
-    } else if message.content.is_empty() || message.content.len() > conf.oprish.message_limit {
-        rate_limiter.wrap_response(Err(ValidationError {
-            field_name: "content".to_string(),
+    };
+    if let Err(err) = validate_content(content, conf.inner()) {
+        return rate_limiter.wrap_response(Err(err));
+    }
+    let message = match Message::edit(channel_id.get(), id.get(), auth.user.id, edit, &mut db).await
+    {
+        Ok(message) => message,
+        Err(err) => return rate_limiter.wrap_response(Err(err)),
+    };
+    let payload = Payload::MessageUpdate(message);
+    payload.publish(&mut *cache).await.unwrap();
+    if let Payload::MessageUpdate(message) = payload {
+        rate_limiter.wrap_response(Ok(Encoded(message)))
+    } else {
+        unreachable!()
+    }
+}
+
+#[delete("/<channel_id>/messages/<id>")]
+#[allow(clippy::too_many_arguments)]
+pub async fn delete(
+    channel_id: Snowflake,
+    id: Snowflake,
+    auth: Authenticated,
+    address: ClientIP,
+    mut cache: Connection<Cache>,
+    mut db: Connection<DB>,
+    conf: &State<Conf>,
+) -> RateLimitedRouteResponse<Result<Status, ErrorResponse>> {
+    let mut rate_limiter = RateLimiter::new("message_delete", address, conf.inner());
+    rate_limiter.process_rate_limit(&mut cache).await?;
+    if let Err(err) = Message::delete(channel_id.get(), id.get(), auth.user.id, &mut db).await {
+        return rate_limiter.wrap_response(Err(err));
+    }
+    Payload::MessageDelete {
+        id: id.get(),
+        channel_id: channel_id.get(),
+    }
+    .publish(&mut *cache)
+    .await
+    .unwrap();
+    rate_limiter.wrap_response(Ok(Status::NoContent))
+}
+
+#[get("/<channel_id>/messages?<before>&<after>&<limit>")]
+#[allow(clippy::too_many_arguments)]
+pub async fn get_messages(
+    channel_id: Snowflake,
+    before: Option<u128>,
+    after: Option<u128>,
+    limit: Option<u32>,
+    address: ClientIP,
+    mut cache: Connection<Cache>,
+    mut db: Connection<DB>,
+    conf: &State<Conf>,
+) -> RateLimitedRouteResponse<Result<Encoded<Vec<Message>>, ErrorResponse>> {
+    let mut rate_limiter = RateLimiter::new("get_messages", address, conf.inner());
+    rate_limiter.process_rate_limit(&mut cache).await?;
+    let limit = limit.unwrap_or(50);
+    if limit == 0 || limit > MESSAGE_HISTORY_LIMIT {
+        return rate_limiter.wrap_response(Err(ValidationError {
+            field_name: "limit".to_string(),
             error: format!(
-                "Message content has to be between 1 and {} characters long.",
-                conf.oprish.message_limit
+                "Message limit has to be between 1 and {}.",
+                MESSAGE_HISTORY_LIMIT
             ),
         }
-        .to_error_response()))
-    } else {
-        let payload = Payload::MessageCreate(message);
-        cache
-            .publish::<&str, String, ()>("oprish-events", serde_json::to_string(&payload).unwrap())
-            .await
-            .unwrap();
-        if let Payload::MessageCreate(message) = payload {
-            rate_limiter.wrap_response(Ok(Json(message)))
-        } else {
-            unreachable!()
-        }
+        .to_error_response()));
     }
+    rate_limiter.wrap_response(
+        Message::get_history(channel_id.get(), before, after, limit, &mut db)
+            .await
+            .map(Encoded),
+    )
 }
 
 pub fn get_routes() -> Vec<Route> {
-    routes![index]
+    routes![index, edit, delete, get_messages]
 }
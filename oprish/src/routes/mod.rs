@@ -1,13 +1,20 @@
+pub mod channels;
 pub mod messages;
 pub mod rate_limits;
+pub mod sessions;
+pub mod users;
 
-use rocket::{serde::json::Json, Route, State};
+use rocket::{Route, State};
 use rocket_db_pools::Connection;
-use todel::{http::ClientIP, models::InstanceInfo, Conf};
+use todel::{
+    http::{ClientIP, Encoded},
+    models::InstanceInfo,
+    Conf,
+};
 
 use crate::{
     rate_limit::{RateLimitedRouteResponse, RateLimiter},
-    Cache, VERSION,
+    Cache,
 }; // poggers
 
 #[get("/")]
@@ -15,20 +22,10 @@ pub async fn index(
     address: ClientIP,
     mut cache: Connection<Cache>,
     conf: &State<Conf>,
-) -> RateLimitedRouteResponse<Json<InstanceInfo>> {
+) -> RateLimitedRouteResponse<Encoded<InstanceInfo>> {
     let mut rate_limiter = RateLimiter::new("info", address, conf.inner());
     rate_limiter.process_rate_limit(&mut cache).await?;
-    rate_limiter.wrap_response(Json(InstanceInfo {
-        instance_name: conf.instance_name.clone(),
-        version: VERSION,
-        description: conf.description.clone(),
-        message_limit: conf.oprish.message_limit,
-        oprish_url: &conf.oprish.url,
-        pandemonium_url: &conf.pandemonium.url,
-        effis_url: &conf.effis.url,
-        file_size: conf.effis.file_size,
-        attachment_file_size: conf.effis.attachment_file_size,
-    }))
+    rate_limiter.wrap_response(Encoded(InstanceInfo::from_conf(conf.inner())))
 }
 
 pub fn get_routes() -> Vec<Route> {
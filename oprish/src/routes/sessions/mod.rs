@@ -0,0 +1,31 @@
+use crate::rate_limit::{RateLimitedRouteResponse, RateLimiter};
+use crate::{Cache, DB};
+use rocket::tokio::sync::Mutex;
+use rocket::{Route, State};
+use rocket_db_pools::Connection;
+use todel::http::{ClientIP, Encoded};
+use todel::ids::IDGenerator;
+use todel::models::{ErrorResponse, Session, SessionCreate, SessionCreated};
+use todel::Conf;
+
+#[post("/", data = "<session>")]
+pub async fn index(
+    session: Encoded<SessionCreate>,
+    address: ClientIP,
+    mut cache: Connection<Cache>,
+    mut db: Connection<DB>,
+    conf: &State<Conf>,
+    gen: &State<Mutex<IDGenerator>>,
+) -> RateLimitedRouteResponse<Result<Encoded<SessionCreated>, ErrorResponse>> {
+    let mut rate_limiter = RateLimiter::new("session_create", address, conf.inner());
+    rate_limiter.process_rate_limit(&mut cache).await?;
+    rate_limiter.wrap_response(
+        Session::create(session.into_inner(), gen.inner(), &mut db)
+            .await
+            .map(Encoded),
+    )
+}
+
+pub fn get_routes() -> Vec<Route> {
+    routes![index]
+}
@@ -0,0 +1,59 @@
+use crate::rate_limit::{RateLimitedRouteResponse, RateLimiter};
+use crate::{Cache, DB};
+use rocket::tokio::sync::Mutex;
+use rocket::{Route, State};
+use rocket_db_pools::Connection;
+use todel::http::{ClientIP, Encoded};
+use todel::ids::IDGenerator;
+use todel::models::{ErrorResponse, ErrorResponseData, User, UserCreate, ValidationError};
+use todel::Conf;
+
+/// Check whether a username is valid.
+fn validate_username(username: &str) -> Result<(), ErrorResponse> {
+    if username.len() < 2 || username.len() > 32 {
+        Err(ValidationError {
+            field_name: "username".to_string(),
+            error: "Username has to be between 2 and 32 characters long.".to_string(),
+        }
+        .to_error_response())
+    } else {
+        Ok(())
+    }
+}
+
+/// Check whether a password is valid.
+fn validate_password(password: &str) -> Result<(), ErrorResponse> {
+    if password.len() < 8 || password.len() > 128 {
+        Err(ValidationError {
+            field_name: "password".to_string(),
+            error: "Password has to be between 8 and 128 characters long.".to_string(),
+        }
+        .to_error_response())
+    } else {
+        Ok(())
+    }
+}
+
+#[post("/", data = "<user>")]
+pub async fn index(
+    user: Encoded<UserCreate>,
+    address: ClientIP,
+    mut cache: Connection<Cache>,
+    mut db: Connection<DB>,
+    conf: &State<Conf>,
+    gen: &State<Mutex<IDGenerator>>,
+) -> RateLimitedRouteResponse<Result<Encoded<User>, ErrorResponse>> {
+    let mut rate_limiter = RateLimiter::new("user_create", address, conf.inner());
+    rate_limiter.process_rate_limit(&mut cache).await?;
+    let user = user.into_inner();
+    if let Err(err) =
+        validate_username(&user.username).and_then(|_| validate_password(&user.password))
+    {
+        return rate_limiter.wrap_response(Err(err));
+    }
+    rate_limiter.wrap_response(User::create(user, gen.inner(), &mut db).await.map(Encoded))
+}
+
+pub fn get_routes() -> Vec<Route> {
+    routes![index]
+}
@@ -1,13 +1,73 @@
 #[cfg(test)]
 mod tests {
-    use crate::{rocket, Cache, VERSION};
+    use crate::{rocket, Cache, DB};
     use deadpool_redis::Connection;
-    use rocket::{futures::StreamExt, http::Status, local::asynchronous::Client};
+    use rocket::{
+        futures::StreamExt,
+        http::{Accept, ContentType, Header, Status},
+        local::asynchronous::Client,
+    };
+    use rocket_db_pools::Database;
+    use std::time::{SystemTime, UNIX_EPOCH};
     use todel::{
-        models::{InstanceInfo, InstanceRateLimits, Message, Payload},
+        models::{
+            Channel, ChannelCreate, ChannelEdit, ErrorResponse, InstanceInfo, InstanceRateLimits,
+            Message, MessageCreate, MessageEdit, Payload, SequencedPayload, SessionCreate,
+            SessionCreated, User, UserCreate,
+        },
         Conf,
     };
 
+    async fn create_user(client: &Client) -> (User, String) {
+        let user = UserCreate {
+            username: format!(
+                "woo{}",
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap()
+                    .as_nanos()
+            ),
+            password: "verysecurepassword".to_string(),
+        };
+        let response = client
+            .post("/users/")
+            .body(serde_json::to_string(&user).unwrap())
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+        let created = response.into_json::<User>().await.unwrap();
+
+        let session = SessionCreate {
+            username: user.username,
+            password: user.password,
+        };
+        let response = client
+            .post("/sessions/")
+            .body(serde_json::to_string(&session).unwrap())
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+        let session = response.into_json::<SessionCreated>().await.unwrap();
+        assert_eq!(session.session.user_id, created.id);
+
+        (created, session.token)
+    }
+
+    async fn create_channel(client: &Client, token: &str) -> Channel {
+        let channel = ChannelCreate {
+            name: "woo".to_string(),
+        };
+        client
+            .post("/channels/")
+            .header(Header::new("Authorization", token.to_string()))
+            .body(serde_json::to_string(&channel).unwrap())
+            .dispatch()
+            .await
+            .into_json::<Channel>()
+            .await
+            .unwrap()
+    }
+
     #[rocket::async_test]
     async fn index() {
         let client = Client::untracked(rocket().unwrap()).await.unwrap();
@@ -18,12 +78,12 @@ mod tests {
             response.into_string().await.unwrap(),
             serde_json::to_string(&InstanceInfo {
                 instance_name: conf.instance_name.clone(),
-                version: VERSION,
+                version: env!("CARGO_PKG_VERSION").to_string(),
                 description: conf.description.clone(),
                 message_limit: conf.oprish.message_limit,
-                oprish_url: &conf.oprish.url,
-                pandemonium_url: &conf.pandemonium.url,
-                effis_url: &conf.effis.url,
+                oprish_url: conf.oprish.url.clone(),
+                pandemonium_url: conf.pandemonium.url.clone(),
+                effis_url: conf.effis.url.clone(),
                 file_size: conf.effis.file_size,
                 attachment_file_size: conf.effis.attachment_file_size,
             })
@@ -31,15 +91,49 @@ mod tests {
         )
     }
 
+    #[rocket::async_test]
+    async fn msgpack_encoding() {
+        let client = Client::untracked(rocket().unwrap()).await.unwrap();
+        let (_, token) = create_user(&client).await;
+        let conf = &client.rocket().state::<Conf>().unwrap();
+        let response = client.get("/").header(Accept::MsgPack).dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.content_type(), Some(ContentType::MsgPack));
+        assert_eq!(
+            rmp_serde::from_slice::<InstanceInfo>(&response.into_bytes().await.unwrap()).unwrap(),
+            InstanceInfo::from_conf(conf)
+        );
+
+        let edit = MessageEdit {
+            content: Some("Woo".to_string()),
+        };
+        let response = client
+            .patch("/channels/0/messages/0")
+            .header(Header::new("Authorization", token.clone()))
+            .header(ContentType::MsgPack)
+            .header(Accept::MsgPack)
+            .body(rmp_serde::to_vec_named(&edit).unwrap())
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::NotFound);
+        assert_eq!(
+            rmp_serde::from_slice::<ErrorResponse>(&response.into_bytes().await.unwrap())
+                .unwrap()
+                .status,
+            404
+        );
+    }
+
     #[rocket::async_test]
     async fn send_message() {
         let client = Client::untracked(rocket().unwrap()).await.unwrap();
-        let message = Message {
-            author: "Woo".to_string(),
+        let (user, token) = create_user(&client).await;
+        let channel = create_channel(&client, &token).await;
+        let message = MessageCreate {
             content: "HeWoo there".to_string(),
+            attachments: vec![],
+            reply_to: None,
         };
-        let message_str = serde_json::to_string(&message).unwrap();
-        let payload = serde_json::to_string(&Payload::MessageCreate(message)).unwrap();
 
         let pool = client.rocket().state::<Cache>().unwrap();
 
@@ -49,24 +143,636 @@ mod tests {
         cache.subscribe("oprish-events").await.unwrap();
 
         let response = client
-            .post("/messages/")
-            .body(&message_str)
+            .post(format!("/channels/{}/messages", channel.id))
+            .header(Header::new("Authorization", token.clone()))
+            .body(serde_json::to_string(&message).unwrap())
             .dispatch()
             .await;
 
         assert_eq!(response.status(), Status::Ok);
-        assert_eq!(response.into_string().await.unwrap(), message_str);
+        let response = response.into_json::<Message>().await.unwrap();
+        assert_eq!(response.channel_id, channel.id);
+        assert_eq!(response.author, user.username);
+        assert_eq!(response.author_id, Some(user.id));
+        assert_eq!(response.content, message.content);
 
+        let event = cache
+            .into_on_message()
+            .next()
+            .await
+            .unwrap()
+            .get_payload::<String>()
+            .unwrap();
         assert_eq!(
-            cache
-                .into_on_message()
-                .next()
-                .await
+            serde_json::from_str::<SequencedPayload>(&event)
                 .unwrap()
-                .get_payload::<String>()
+                .payload,
+            Payload::MessageCreate(response)
+        );
+    }
+
+    #[rocket::async_test]
+    async fn get_messages() {
+        let client = Client::untracked(rocket().unwrap()).await.unwrap();
+        let (_, token) = create_user(&client).await;
+        let channel = create_channel(&client, &token).await;
+        let mut messages = vec![];
+        for content in ["Woo", "Wee", "Waa"] {
+            let message = MessageCreate {
+                content: content.to_string(),
+                attachments: vec![],
+                reply_to: messages.last().map(|m: &Message| m.id),
+            };
+            let response = client
+                .post(format!("/channels/{}/messages", channel.id))
+                .header(Header::new("Authorization", token.clone()))
+                .body(serde_json::to_string(&message).unwrap())
+                .dispatch()
+                .await;
+            messages.push(response.into_json::<Message>().await.unwrap());
+        }
+
+        let response = client
+            .get(format!(
+                "/channels/{}/messages?before={}",
+                channel.id, messages[2].id
+            ))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+        let history = response.into_json::<Vec<Message>>().await.unwrap();
+        assert_eq!(history[history.len() - 2..], messages[..2]);
+
+        let response = client
+            .get(format!(
+                "/channels/{}/messages?after={}&limit=1",
+                channel.id, messages[0].id
+            ))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(
+            response.into_json::<Vec<Message>>().await.unwrap(),
+            messages[1..2]
+        );
+
+        let response = client
+            .get(format!("/channels/{}/messages?limit=0", channel.id))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+
+        let response = client.get("/channels/0/messages").dispatch().await;
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
+    #[rocket::async_test]
+    async fn get_messages_with_attachments() {
+        let client = Client::untracked(rocket().unwrap()).await.unwrap();
+        let (_, token) = create_user(&client).await;
+        let channel = create_channel(&client, &token).await;
+        let first_id = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        let mut db = DB::fetch(client.rocket()).unwrap().acquire().await.unwrap();
+        for id in [first_id, first_id + 1, first_id + 2] {
+            sqlx::query(
+                "
+INSERT INTO files(id, file_id, name, content_type, hash, bucket)
+VALUES(?, ?, ?, 'text/plain', ?, 'attachments')
+                ",
+            )
+            .bind(id.to_string())
+            .bind(id.to_string())
+            .bind(format!("{}.txt", id))
+            .bind(id.to_string())
+            .execute(&mut db)
+            .await
+            .unwrap();
+        }
+
+        let mut messages = vec![];
+        for attachments in [vec![first_id, first_id + 1], vec![], vec![first_id + 2]] {
+            let message = MessageCreate {
+                content: "Woo".to_string(),
+                attachments,
+                reply_to: None,
+            };
+            let response = client
+                .post(format!("/channels/{}/messages", channel.id))
+                .header(Header::new("Authorization", token.clone()))
+                .body(serde_json::to_string(&message).unwrap())
+                .dispatch()
+                .await;
+            assert_eq!(response.status(), Status::Ok);
+            messages.push(response.into_json::<Message>().await.unwrap());
+        }
+        assert_eq!(messages[0].attachments.len(), 2);
+
+        let response = client
+            .get(format!("/channels/{}/messages", channel.id))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(
+            response.into_json::<Vec<Message>>().await.unwrap(),
+            messages
+        );
+
+        let edit = MessageEdit {
+            content: Some("Wee".to_string()),
+        };
+        let response = client
+            .patch(format!(
+                "/channels/{}/messages/{}",
+                channel.id, messages[0].id
+            ))
+            .header(Header::new("Authorization", token.clone()))
+            .body(serde_json::to_string(&edit).unwrap())
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(
+            response.into_json::<Message>().await.unwrap().attachments,
+            messages[0].attachments
+        );
+    }
+
+    #[rocket::async_test]
+    async fn send_message_unknown_references() {
+        let client = Client::untracked(rocket().unwrap()).await.unwrap();
+        let (_, token) = create_user(&client).await;
+        let channel = create_channel(&client, &token).await;
+        let message = MessageCreate {
+            content: "HeWoo there".to_string(),
+            attachments: vec![0],
+            reply_to: None,
+        };
+        let response = client
+            .post(format!("/channels/{}/messages", channel.id))
+            .header(Header::new("Authorization", token.clone()))
+            .body(serde_json::to_string(&message).unwrap())
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+
+        let message = MessageCreate {
+            attachments: vec![],
+            reply_to: Some(0),
+            ..message
+        };
+        let response = client
+            .post(format!("/channels/{}/messages", channel.id))
+            .header(Header::new("Authorization", token.clone()))
+            .body(serde_json::to_string(&message).unwrap())
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+
+        let message = MessageCreate {
+            reply_to: None,
+            ..message
+        };
+        let response = client
+            .post("/channels/0/messages")
+            .header(Header::new("Authorization", token.clone()))
+            .body(serde_json::to_string(&message).unwrap())
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
+    #[rocket::async_test]
+    async fn edit_message() {
+        let client = Client::untracked(rocket().unwrap()).await.unwrap();
+        let (_, token) = create_user(&client).await;
+        let channel = create_channel(&client, &token).await;
+        let message = MessageCreate {
+            content: "HeWoo thera".to_string(),
+            attachments: vec![],
+            reply_to: None,
+        };
+        let message = client
+            .post(format!("/channels/{}/messages", channel.id))
+            .header(Header::new("Authorization", token.clone()))
+            .body(serde_json::to_string(&message).unwrap())
+            .dispatch()
+            .await
+            .into_json::<Message>()
+            .await
+            .unwrap();
+
+        let pool = client.rocket().state::<Cache>().unwrap();
+
+        let cache = pool.get().await.unwrap();
+        let cache = Connection::take(cache);
+        let mut cache = cache.into_pubsub();
+        cache.subscribe("oprish-events").await.unwrap();
+
+        let edit = MessageEdit {
+            content: Some("HeWoo there".to_string()),
+        };
+        let response = client
+            .patch(format!("/channels/{}/messages/{}", channel.id, message.id))
+            .header(Header::new("Authorization", token.clone()))
+            .body(serde_json::to_string(&edit).unwrap())
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+        let response = response.into_json::<Message>().await.unwrap();
+        assert_eq!(
+            response,
+            Message {
+                content: "HeWoo there".to_string(),
+                ..message.clone()
+            }
+        );
+
+        let event = cache
+            .into_on_message()
+            .next()
+            .await
+            .unwrap()
+            .get_payload::<String>()
+            .unwrap();
+        assert_eq!(
+            serde_json::from_str::<SequencedPayload>(&event)
+                .unwrap()
+                .payload,
+            Payload::MessageUpdate(response)
+        );
+
+        let edit = MessageEdit { content: None };
+        let response = client
+            .patch(format!("/channels/{}/messages/{}", channel.id, message.id))
+            .header(Header::new("Authorization", token.clone()))
+            .body(serde_json::to_string(&edit).unwrap())
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+
+        let edit = MessageEdit {
+            content: Some("HeWoo there".to_string()),
+        };
+        let response = client
+            .patch(format!("/channels/{}/messages/0", channel.id))
+            .header(Header::new("Authorization", token.clone()))
+            .body(serde_json::to_string(&edit).unwrap())
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::NotFound);
+
+        let response = client
+            .patch(format!("/channels/0/messages/{}", message.id))
+            .header(Header::new("Authorization", token.clone()))
+            .body(serde_json::to_string(&edit).unwrap())
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::NotFound);
+
+        let (_, other_token) = create_user(&client).await;
+        let response = client
+            .patch(format!("/channels/{}/messages/{}", channel.id, message.id))
+            .header(Header::new("Authorization", other_token))
+            .body(serde_json::to_string(&edit).unwrap())
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Forbidden);
+
+        let response = client
+            .patch(format!("/channels/{}/messages/{}", channel.id, message.id))
+            .body(serde_json::to_string(&edit).unwrap())
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Unauthorized);
+        assert_eq!(
+            response.into_json::<ErrorResponse>().await.unwrap().status,
+            401
+        );
+    }
+
+    #[rocket::async_test]
+    async fn delete_message() {
+        let client = Client::untracked(rocket().unwrap()).await.unwrap();
+        let (_, token) = create_user(&client).await;
+        let channel = create_channel(&client, &token).await;
+        let message = MessageCreate {
+            content: "Byebye".to_string(),
+            attachments: vec![],
+            reply_to: None,
+        };
+        let message = client
+            .post(format!("/channels/{}/messages", channel.id))
+            .header(Header::new("Authorization", token.clone()))
+            .body(serde_json::to_string(&message).unwrap())
+            .dispatch()
+            .await
+            .into_json::<Message>()
+            .await
+            .unwrap();
+
+        let pool = client.rocket().state::<Cache>().unwrap();
+
+        let cache = pool.get().await.unwrap();
+        let cache = Connection::take(cache);
+        let mut cache = cache.into_pubsub();
+        cache.subscribe("oprish-events").await.unwrap();
+
+        let response = client
+            .delete(format!("/channels/{}/messages/{}", channel.id, message.id))
+            .header(Header::new("Authorization", token.clone()))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::NoContent);
+
+        let event = cache
+            .into_on_message()
+            .next()
+            .await
+            .unwrap()
+            .get_payload::<String>()
+            .unwrap();
+        assert_eq!(
+            serde_json::from_str::<SequencedPayload>(&event)
+                .unwrap()
+                .payload,
+            Payload::MessageDelete {
+                id: message.id,
+                channel_id: channel.id,
+            }
+        );
+
+        let response = client
+            .delete(format!("/channels/{}/messages/{}", channel.id, message.id))
+            .header(Header::new("Authorization", token.clone()))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
+    #[rocket::async_test]
+    async fn users() {
+        let client = Client::untracked(rocket().unwrap()).await.unwrap();
+        let (user, _) = create_user(&client).await;
+
+        let response = client
+            .post("/users/")
+            .body(
+                serde_json::to_string(&UserCreate {
+                    username: user.username.clone(),
+                    password: "verysecurepassword".to_string(),
+                })
+                .unwrap(),
+            )
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+
+        let response = client
+            .post("/users/")
+            .body(
+                serde_json::to_string(&UserCreate {
+                    username: "woo".to_string(),
+                    password: "short".to_string(),
+                })
+                .unwrap(),
+            )
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+
+        let response = client
+            .post("/sessions/")
+            .body(
+                serde_json::to_string(&SessionCreate {
+                    username: user.username,
+                    password: "wrongpassword".to_string(),
+                })
+                .unwrap(),
+            )
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Unauthorized);
+
+        let response = client
+            .post("/channels/0/messages")
+            .header(Header::new("Authorization", "invalid"))
+            .body(
+                serde_json::to_string(&MessageCreate {
+                    content: "Woo".to_string(),
+                    attachments: vec![],
+                    reply_to: None,
+                })
                 .unwrap(),
-            payload
+            )
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Unauthorized);
+        assert_eq!(
+            response.into_json::<ErrorResponse>().await.unwrap().status,
+            401
+        );
+    }
+
+    #[rocket::async_test]
+    async fn channels() {
+        let client = Client::untracked(rocket().unwrap()).await.unwrap();
+        let (user, token) = create_user(&client).await;
+        let (_, other_token) = create_user(&client).await;
+
+        let pool = client.rocket().state::<Cache>().unwrap();
+
+        let cache = pool.get().await.unwrap();
+        let cache = Connection::take(cache);
+        let mut cache = cache.into_pubsub();
+        cache.subscribe("oprish-events").await.unwrap();
+        let mut events = cache.into_on_message();
+
+        let channel = create_channel(&client, &token).await;
+        assert_eq!(channel.name, "woo");
+        assert_eq!(channel.owner_id, Some(user.id));
+        let event = events
+            .next()
+            .await
+            .unwrap()
+            .get_payload::<String>()
+            .unwrap();
+        assert_eq!(
+            serde_json::from_str::<SequencedPayload>(&event)
+                .unwrap()
+                .payload,
+            Payload::ChannelCreate(channel.clone())
+        );
+
+        let response = client
+            .get(format!("/channels/{}", channel.id))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.into_json::<Channel>().await.unwrap(), channel);
+
+        let edit = ChannelEdit {
+            name: Some("wee".to_string()),
+        };
+        let response = client
+            .patch(format!("/channels/{}", channel.id))
+            .body(serde_json::to_string(&edit).unwrap())
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Unauthorized);
+        assert_eq!(
+            response.into_json::<ErrorResponse>().await.unwrap().status,
+            401
+        );
+        let response = client
+            .patch(format!("/channels/{}", channel.id))
+            .header(Header::new("Authorization", other_token.clone()))
+            .body(serde_json::to_string(&edit).unwrap())
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Forbidden);
+        let response = client
+            .patch(format!("/channels/{}", channel.id))
+            .header(Header::new("Authorization", token.clone()))
+            .body(serde_json::to_string(&edit).unwrap())
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+        let channel = response.into_json::<Channel>().await.unwrap();
+        assert_eq!(channel.name, "wee");
+        let event = events
+            .next()
+            .await
+            .unwrap()
+            .get_payload::<String>()
+            .unwrap();
+        assert_eq!(
+            serde_json::from_str::<SequencedPayload>(&event)
+                .unwrap()
+                .payload,
+            Payload::ChannelUpdate(channel.clone())
+        );
+
+        let edit = ChannelEdit {
+            name: Some("".to_string()),
+        };
+        let response = client
+            .patch(format!("/channels/{}", channel.id))
+            .header(Header::new("Authorization", token.clone()))
+            .body(serde_json::to_string(&edit).unwrap())
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+
+        let response = client.get("/channels/").dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        assert!(response
+            .into_json::<Vec<Channel>>()
+            .await
+            .unwrap()
+            .contains(&channel));
+
+        let response = client
+            .delete(format!("/channels/{}", channel.id))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Unauthorized);
+        assert_eq!(
+            response.into_json::<ErrorResponse>().await.unwrap().status,
+            401
+        );
+        let response = client
+            .delete(format!("/channels/{}", channel.id))
+            .header(Header::new("Authorization", other_token.clone()))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Forbidden);
+        // The general channel has no owner so it can't be deleted.
+        let response = client
+            .delete("/channels/1")
+            .header(Header::new("Authorization", token.clone()))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Forbidden);
+        let response = client
+            .delete(format!("/channels/{}", channel.id))
+            .header(Header::new("Authorization", token.clone()))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::NoContent);
+        let event = events
+            .next()
+            .await
+            .unwrap()
+            .get_payload::<String>()
+            .unwrap();
+        assert_eq!(
+            serde_json::from_str::<SequencedPayload>(&event)
+                .unwrap()
+                .payload,
+            Payload::ChannelDelete { id: channel.id }
         );
+
+        let response = client
+            .get(format!("/channels/{}", channel.id))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
+    #[rocket::async_test]
+    async fn typing() {
+        let client = Client::untracked(rocket().unwrap()).await.unwrap();
+        let (user, token) = create_user(&client).await;
+        let channel = create_channel(&client, &token).await;
+
+        let pool = client.rocket().state::<Cache>().unwrap();
+
+        let cache = pool.get().await.unwrap();
+        let cache = Connection::take(cache);
+        let mut cache = cache.into_pubsub();
+        cache.subscribe("oprish-events").await.unwrap();
+        let mut events = cache.into_on_message();
+
+        let response = client
+            .post(format!("/channels/{}/typing", channel.id))
+            .header(Header::new("Authorization", token.clone()))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::NoContent);
+        let event = events
+            .next()
+            .await
+            .unwrap()
+            .get_payload::<String>()
+            .unwrap();
+        assert_eq!(
+            serde_json::from_str::<SequencedPayload>(&event)
+                .unwrap()
+                .payload,
+            Payload::TypingStart {
+                channel_id: channel.id,
+                user,
+            }
+        );
+
+        let response = client
+            .post(format!("/channels/{}/typing", channel.id))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Unauthorized);
+        assert_eq!(
+            response.into_json::<ErrorResponse>().await.unwrap().status,
+            401
+        );
+
+        let response = client
+            .post("/channels/1234/typing")
+            .header(Header::new("Authorization", token))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::NotFound);
     }
 
     #[rocket::async_test]
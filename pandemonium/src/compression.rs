@@ -0,0 +1,101 @@
+use std::io::{self, Write};
+
+use flate2::{write::ZlibEncoder, Compression as CompressionLevel};
+use tokio_tungstenite::tungstenite::Message as WebSocketMessage;
+
+use crate::utils::get_query_param;
+
+/// The compression a client opted in to with the `compress` query parameter.
+pub enum Compression {
+    None,
+    /// A single zlib context shared by every payload sent to the client.
+    ///
+    /// Each payload is flushed on its own so it ends with the `00 00 ff ff` suffix.
+    ZlibStream(ZlibEncoder<Vec<u8>>),
+}
+
+impl Compression {
+    /// Gets the compression requested in a connection's query string.
+    pub fn from_query(query: Option<&str>) -> Result<Self, String> {
+        match get_query_param(query, "compress") {
+            None => Ok(Self::None),
+            Some("zlib-stream") => Ok(Self::ZlibStream(ZlibEncoder::new(
+                vec![],
+                CompressionLevel::default(),
+            ))),
+            Some(compress) => Err(format!("Unsupported compression {}", compress)),
+        }
+    }
+
+    /// Compresses a websocket message, compressed messages are always sent as binary ones.
+    pub fn compress(&mut self, message: WebSocketMessage) -> io::Result<WebSocketMessage> {
+        match self {
+            Self::None => Ok(message),
+            Self::ZlibStream(encoder) => {
+                encoder.write_all(&message.into_data())?;
+                encoder.flush()?;
+                Ok(WebSocketMessage::Binary(std::mem::take(encoder.get_mut())))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use flate2::{Decompress, FlushDecompress};
+    use tokio_tungstenite::tungstenite::Message as WebSocketMessage;
+
+    use super::Compression;
+
+    #[test]
+    fn from_query() {
+        assert!(matches!(
+            Compression::from_query(None),
+            Ok(Compression::None)
+        ));
+        assert!(matches!(
+            Compression::from_query(Some("encoding=json")),
+            Ok(Compression::None)
+        ));
+        assert!(matches!(
+            Compression::from_query(Some("compress=zlib-stream")),
+            Ok(Compression::ZlibStream(_))
+        ));
+        assert!(matches!(
+            Compression::from_query(Some("compress=gzip")),
+            Err(err) if err == "Unsupported compression gzip"
+        ));
+    }
+
+    #[test]
+    fn zlib_stream() {
+        let mut compression = Compression::from_query(Some("compress=zlib-stream")).unwrap();
+        let mut decompress = Decompress::new(true);
+        for text in [r#"{"op":"PONG"}"#, r#"{"op":"PONG"}"#, "woo"] {
+            let data = match compression
+                .compress(WebSocketMessage::Text(text.to_string()))
+                .unwrap()
+            {
+                WebSocketMessage::Binary(data) => data,
+                message => panic!("Compressed message is not binary: {:?}", message),
+            };
+            assert!(data.ends_with(&[0x00, 0x00, 0xff, 0xff]));
+            // Every message can be decompressed on its own with the context shared by the
+            // previous ones.
+            let mut decompressed = Vec::with_capacity(64);
+            decompress
+                .decompress_vec(&data, &mut decompressed, FlushDecompress::Sync)
+                .unwrap();
+            assert_eq!(decompressed, text.as_bytes());
+        }
+    }
+
+    #[test]
+    fn no_compression() {
+        let message = WebSocketMessage::Text("woo".to_string());
+        assert_eq!(
+            Compression::None.compress(message.clone()).unwrap(),
+            message
+        );
+    }
+}
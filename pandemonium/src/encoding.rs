@@ -0,0 +1,113 @@
+use serde::{de::DeserializeOwned, Serialize};
+use tokio_tungstenite::tungstenite::Message as WebSocketMessage;
+
+use crate::utils::get_query_param;
+
+/// The encoding a client opted in to with the `encoding` query parameter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Json,
+    /// MessagePack payloads, sent as binary frames.
+    MsgPack,
+}
+
+impl Encoding {
+    /// Gets the encoding requested in a connection's query string.
+    pub fn from_query(query: Option<&str>) -> Result<Self, String> {
+        match get_query_param(query, "encoding") {
+            None | Some("json") => Ok(Self::Json),
+            Some("msgpack") => Ok(Self::MsgPack),
+            Some(encoding) => Err(format!("Unsupported encoding {}", encoding)),
+        }
+    }
+
+    /// Serializes a payload into a websocket message.
+    pub fn serialize<T: Serialize>(self, payload: &T) -> WebSocketMessage {
+        match self {
+            Self::Json => WebSocketMessage::Text(
+                serde_json::to_string(payload).expect("Couldn't serialize payload"),
+            ),
+            Self::MsgPack => WebSocketMessage::Binary(
+                rmp_serde::to_vec_named(payload).expect("Couldn't serialize payload"),
+            ),
+        }
+    }
+
+    /// Deserializes a payload from a websocket message.
+    ///
+    /// Text messages are always treated as JSON, binary messages are only accepted from clients
+    /// using MessagePack.
+    pub fn deserialize<T: DeserializeOwned>(self, message: &WebSocketMessage) -> Result<T, String> {
+        match (self, message) {
+            (_, WebSocketMessage::Text(message)) => {
+                serde_json::from_str(message).map_err(|e| e.to_string())
+            }
+            (Self::MsgPack, WebSocketMessage::Binary(message)) => {
+                rmp_serde::from_slice(message).map_err(|e| e.to_string())
+            }
+            _ => Err("Unsupported message type".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use todel::models::{Payload, SequencedPayload};
+    use tokio_tungstenite::tungstenite::Message as WebSocketMessage;
+
+    use super::Encoding;
+
+    #[test]
+    fn from_query() {
+        assert_eq!(Encoding::from_query(None), Ok(Encoding::Json));
+        assert_eq!(Encoding::from_query(Some("v=1")), Ok(Encoding::Json));
+        assert_eq!(
+            Encoding::from_query(Some("encoding=json")),
+            Ok(Encoding::Json)
+        );
+        assert_eq!(
+            Encoding::from_query(Some("compress=zlib-stream&encoding=msgpack")),
+            Ok(Encoding::MsgPack)
+        );
+        assert_eq!(
+            Encoding::from_query(Some("encoding=cbor")),
+            Err("Unsupported encoding cbor".to_string())
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let payload = SequencedPayload {
+            seq: 42,
+            payload: Payload::MessageDelete {
+                id: 1234,
+                channel_id: 1,
+            },
+        };
+        for encoding in [Encoding::Json, Encoding::MsgPack] {
+            let message = encoding.serialize(&payload);
+            assert_eq!(
+                matches!(message, WebSocketMessage::Binary(_)),
+                encoding == Encoding::MsgPack
+            );
+            assert_eq!(
+                encoding.deserialize::<SequencedPayload>(&message),
+                Ok(payload.clone())
+            );
+        }
+    }
+
+    #[test]
+    fn message_types() {
+        let ping = Encoding::Json.serialize(&Payload::Ping);
+        assert_eq!(
+            Encoding::MsgPack.deserialize::<Payload>(&ping),
+            Ok(Payload::Ping)
+        );
+        let ping = Encoding::MsgPack.serialize(&Payload::Ping);
+        assert_eq!(
+            Encoding::Json.deserialize::<Payload>(&ping),
+            Err("Unsupported message type".to_string())
+        );
+    }
+}
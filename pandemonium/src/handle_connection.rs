@@ -1,30 +1,64 @@
-use deadpool_redis::redis::aio::PubSub;
 use deadpool_redis::Connection;
 use futures::stream::{SplitSink, SplitStream};
 use futures::{SinkExt, StreamExt};
+use serde::Serialize;
+use sqlx::MySqlPool;
 use std::borrow::Cow;
+use std::collections::HashSet;
 use std::net::{IpAddr, SocketAddr};
 use std::str::FromStr;
 use std::sync::Arc;
 use std::time::Duration;
-use todel::models::Payload;
+use todel::models::{GatewayCloseCode, InstanceInfo, Payload, SequencedPayload, Session, User};
 use todel::Conf;
 use tokio::net::TcpStream;
-use tokio::sync::Mutex;
+use tokio::sync::broadcast::{error::RecvError, Receiver};
+use tokio::sync::{watch, Mutex};
 use tokio::time::{interval, Instant};
-use tokio_tungstenite::tungstenite::handshake::server::{Request, Response};
+use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tokio_tungstenite::tungstenite::http::StatusCode;
 use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
 use tokio_tungstenite::tungstenite::protocol::CloseFrame;
-use tokio_tungstenite::tungstenite::Message as WebSocketMessage;
+use tokio_tungstenite::tungstenite::{Error as WebSocketError, Message as WebSocketMessage};
 use tokio_tungstenite::{accept_hdr_async, WebSocketStream};
 
+use crate::compression::Compression;
+use crate::encoding::Encoding;
+use crate::presence::{add_presence, online_users, remove_presence, renew_presence};
 use crate::rate_limit::RateLimiter;
-use crate::utils::deserialize_message;
+use crate::session::{create_session, delete_session, get_session_owner, renew_session};
 
+/// The interval at which clients are expected to send pings, sent to them in the `Hello` payload.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(45);
 /// The duration it takes for a connection to be inactive in for it to be regarded as zombified and
 /// disconnected.
 const TIMEOUT_DURATION: Duration = Duration::from_secs(46); // 45 seconds + some time to account
                                                             // for jitter
+/// The sequence numbers of the first and last events dispatched to a client along with the
+/// channels it subscribed to.
+#[derive(Debug, Default)]
+struct DispatchState {
+    first_seq: Option<u64>,
+    last_seq: Option<u64>,
+    /// The channels the client receives message events from, `None` means all of them.
+    channels: Option<HashSet<u128>>,
+    /// Whether the client receives events at all, only unidentified clients may not.
+    allowed: bool,
+}
+
+impl DispatchState {
+    /// Whether an event should be sent to the client.
+    fn is_subscribed(&self, payload: &Payload) -> bool {
+        if !self.allowed {
+            return false;
+        }
+        match (&self.channels, payload.channel_id()) {
+            (Some(channels), Some(channel_id)) => channels.contains(&channel_id),
+            _ => true,
+        }
+    }
+}
+
 /// A simple function that check's if a client's last ping was over TIMEOUT_DURATION seconds ago and
 /// closes the gateway connection if so.
 async fn check_connection(last_ping: Arc<Mutex<Instant>>) {
@@ -37,17 +71,87 @@ async fn check_connection(last_ping: Arc<Mutex<Instant>>) {
     }
 }
 
+/// Serializes a payload and sends it to a client using its negotiated encoding and compression.
+async fn send_payload<T: Serialize>(
+    tx: &mut SplitSink<WebSocketStream<TcpStream>, WebSocketMessage>,
+    encoding: Encoding,
+    compression: &Mutex<Compression>,
+    payload: &T,
+) -> Result<(), WebSocketError> {
+    let message = compression
+        .lock()
+        .await
+        .compress(encoding.serialize(payload))
+        .map_err(WebSocketError::Io)?;
+    tx.send(message).await
+}
+
+/// Gets why a payload a client sent is rejected, which is sent back to it in an `Error` payload.
+fn rejection_reason(payload: Result<Payload, String>) -> String {
+    match payload {
+        Ok(Payload::Identify { .. }) => "Already identified".to_string(),
+        Ok(Payload::Resume { .. }) => "Clients have to identify before resuming".to_string(),
+        Ok(_) => "Unsupported payload".to_string(),
+        Err(err) => err,
+    }
+}
+
+/// Builds the close frame sent along with a gateway close code.
+fn close_frame(code: GatewayCloseCode) -> CloseFrame<'static> {
+    CloseFrame {
+        code: CloseCode::from(u16::from(code)),
+        reason: Cow::Borrowed(code.reason()),
+    }
+}
+
+/// Gets the session and user a token belongs to, checking the cache before the database.
+async fn authenticate(
+    token: &str,
+    cache: &mut Connection,
+    db: &MySqlPool,
+) -> Result<(Session, User), GatewayCloseCode> {
+    if let Some(session) = Session::get_cached(token, cache).await {
+        return Ok(session);
+    }
+    let mut conn = db.acquire().await.map_err(|err| {
+        log::error!("Couldn't acquire a database connection: {}", err);
+        GatewayCloseCode::ServerError
+    })?;
+    match Session::validate(token, &mut conn).await {
+        Ok((session, user)) => {
+            session.cache(token, &user, cache).await;
+            Ok((session, user))
+        }
+        Err(err) if err.status == 401 => Err(GatewayCloseCode::AuthenticationFailed),
+        Err(_) => Err(GatewayCloseCode::ServerError),
+    }
+}
+
+/// Get the users which are currently online.
+async fn get_online_users(cache: &mut Connection, db: &MySqlPool) -> Result<Vec<User>, String> {
+    let ids = online_users(cache).await.map_err(|e| e.to_string())?;
+    let mut conn = db.acquire().await.map_err(|e| e.to_string())?;
+    User::get_many(&ids, &mut conn).await.map_err(|e| e.message)
+}
+
 /// A function that handles one client connecting and disconnecting.
+#[allow(clippy::result_large_err)] // the handshake callback has to return tungstenite's response
+#[allow(clippy::too_many_arguments)]
 pub async fn handle_connection(
     stream: TcpStream,
     addr: SocketAddr,
     cache: Connection,
-    pubsub: PubSub,
+    mut session_cache: Connection,
+    db: MySqlPool,
+    mut events: Receiver<SequencedPayload>,
+    mut shutdown: watch::Receiver<()>,
     conf: Arc<Conf>,
 ) {
     let mut rl_address = IpAddr::from_str("127.0.0.1").unwrap();
+    let mut encoding = Encoding::Json;
+    let mut compression = Compression::None;
 
-    let socket = match accept_hdr_async(stream, |req: &Request, resp: Response| {
+    let mut socket = match accept_hdr_async(stream, |req: &Request, resp: Response| {
         let headers = req.headers();
 
         if let Some(ip) = headers.get("X-Real-Ip") {
@@ -64,6 +168,14 @@ pub async fn handle_connection(
             rl_address = addr.ip();
         }
 
+        let bad_request = |err| {
+            let mut resp = ErrorResponse::new(Some(err));
+            *resp.status_mut() = StatusCode::BAD_REQUEST;
+            resp
+        };
+        encoding = Encoding::from_query(req.uri().query()).map_err(bad_request)?;
+        compression = Compression::from_query(req.uri().query()).map_err(bad_request)?;
+
         Ok(resp)
     })
     .await
@@ -90,13 +202,58 @@ pub async fn handle_connection(
             "Disconnected a client: {}, reason: Hit rate_limit",
             rl_address
         );
+        if let Err(err) = socket
+            .close(Some(close_frame(GatewayCloseCode::RateLimited)))
+            .await
+        {
+            log::debug!("Couldn't close socket with {}: {}", rl_address, err);
+        }
         return;
     }
 
+    let mut session_id = match create_session(&mut session_cache).await {
+        Ok(session_id) => session_id,
+        Err(err) => {
+            log::error!("Could not create a session for {}: {}", rl_address, err);
+            if let Err(err) = socket
+                .close(Some(close_frame(GatewayCloseCode::ServerError)))
+                .await
+            {
+                log::debug!("Couldn't close socket with {}: {}", rl_address, err);
+            }
+            return;
+        }
+    };
+
     let (tx, mut rx) = socket.split();
     let tx = Arc::new(Mutex::new(tx));
+    let compression = Mutex::new(compression);
+
+    let hello = Payload::Hello {
+        heartbeat_interval: HEARTBEAT_INTERVAL.as_millis() as u64,
+        instance_info: InstanceInfo::from_conf(&conf),
+        session_id: session_id.clone(),
+    };
+    let res = send_payload(&mut *tx.lock().await, encoding, &compression, &hello).await;
+    if let Err(err) = res {
+        log::error!(
+            "Could not send gateway HELLO frame to {}: {}",
+            rl_address,
+            err
+        );
+        close_socket(tx, rx, GatewayCloseCode::ServerError, rl_address).await;
+        return;
+    }
 
     let last_ping = Arc::new(Mutex::new(Instant::now()));
+    let dispatched = Mutex::new(DispatchState {
+        allowed: conf.pandemonium.allow_unauthenticated,
+        ..Default::default()
+    });
+    let mut invalid_payloads = 0;
+    let mut user: Option<User> = None;
+    // The session ID changes when resuming, so presence is tracked by the original one.
+    let connection_id = session_id.clone();
 
     let handle_rx = async {
         while let Some(msg) = rx.next().await {
@@ -106,77 +263,271 @@ pub async fn handle_connection(
                     "Disconnected a client: {}, reason: Hit rate_limit",
                     rl_address
                 );
-                break;
+                return Some(GatewayCloseCode::RateLimited);
             }
             match msg {
-                Ok(data) => match data {
-                    WebSocketMessage::Text(message) => {
-                        match serde_json::from_str::<Payload>(&message) {
-                            Ok(Payload::Ping) => {
-                                let mut last_ping = last_ping.lock().await;
-                                *last_ping = Instant::now();
-                                let res = tx
-                                    .lock()
-                                    .await
-                                    .send(WebSocketMessage::Text(
-                                        serde_json::to_string(&Payload::Pong).unwrap(),
-                                    ))
-                                    .await;
-                                if let Err(err) = res {
-                                    log::error!("Could not send gateway PONG frame: {}", err);
+                // Control frames are handled by tungstenite.
+                Ok(
+                    WebSocketMessage::Ping(_)
+                    | WebSocketMessage::Pong(_)
+                    | WebSocketMessage::Close(_)
+                    | WebSocketMessage::Frame(_),
+                ) => {}
+                Ok(data) => match encoding.deserialize::<Payload>(&data) {
+                    Ok(Payload::Ping) => {
+                        let mut last_ping = last_ping.lock().await;
+                        *last_ping = Instant::now();
+                        let res = send_payload(
+                            &mut *tx.lock().await,
+                            encoding,
+                            &compression,
+                            &Payload::Pong,
+                        )
+                        .await;
+                        if let Err(err) = res {
+                            log::error!("Could not send gateway PONG frame: {}", err);
+                        }
+                        let user_id = user.as_ref().map(|user| user.id);
+                        if let Err(err) =
+                            renew_session(&session_id, user_id, &mut session_cache).await
+                        {
+                            log::warn!("Failed to renew session {}: {}", session_id, err);
+                        }
+                        if let Some(user) = &user {
+                            if let Err(err) = renew_presence(user, &mut session_cache).await {
+                                log::warn!("Failed to renew presence of {}: {}", user.id, err);
+                            }
+                        }
+                    }
+                    Ok(Payload::Resume {
+                        session_id: resumed_id,
+                        seq,
+                    }) if user.is_some() || conf.pandemonium.allow_unauthenticated => {
+                        let mut tx = tx.lock().await;
+                        let mut dispatched = dispatched.lock().await;
+                        let user_id = user.as_ref().map(|user| user.id);
+                        // Sessions of other users are treated like missing ones so that clients
+                        // can't tell whether they exist.
+                        let events = match get_session_owner(&resumed_id, &mut session_cache).await
+                        {
+                            Ok(Some(owner)) if owner.can_resume(user_id) => {
+                                SequencedPayload::get_after(seq, &mut session_cache).await
+                            }
+                            Ok(_) => Ok(None),
+                            Err(err) => Err(err),
+                        };
+                        let events = match events {
+                            Ok(Some(events)) => events,
+                            Ok(None) => {
+                                log::debug!(
+                                    "Client {} failed to resume session {}",
+                                    rl_address,
+                                    resumed_id
+                                );
+                                if let Err(err) = send_payload(
+                                    &mut tx,
+                                    encoding,
+                                    &compression,
+                                    &Payload::InvalidSession,
+                                )
+                                .await
+                                {
+                                    log::error!(
+                                        "Could not send gateway INVALID_SESSION frame: {}",
+                                        err
+                                    );
                                 }
+                                continue;
                             }
-                            _ => log::debug!("Unknown gateway payload: {}", message),
+                            Err(err) => {
+                                log::error!(
+                                    "Failed to fetch the events of session {}: {}",
+                                    resumed_id,
+                                    err
+                                );
+                                continue;
+                            }
+                        };
+                        // Events dispatched since this connection was opened were
+                        // already sent to the client.
+                        let first_seq = dispatched.first_seq;
+                        for event in events
+                            .into_iter()
+                            .filter(|e| !matches!(first_seq, Some(first) if e.seq >= first))
+                        {
+                            if !dispatched.is_subscribed(&event.payload) {
+                                continue;
+                            }
+                            if let Err(err) =
+                                send_payload(&mut tx, encoding, &compression, &event).await
+                            {
+                                log::warn!("Failed to send payload to {}: {}", rl_address, err);
+                                break;
+                            }
+                            dispatched.last_seq = dispatched.last_seq.max(Some(event.seq));
+                        }
+                        if let Err(err) = delete_session(&session_id, &mut session_cache).await {
+                            log::warn!("Failed to delete session {}: {}", session_id, err);
+                        }
+                        session_id = resumed_id;
+                        if let Err(err) =
+                            renew_session(&session_id, user_id, &mut session_cache).await
+                        {
+                            log::warn!("Failed to renew session {}: {}", session_id, err);
+                        }
+                    }
+                    Ok(Payload::Identify { token }) if user.is_none() => {
+                        let identified = match authenticate(&token, &mut session_cache, &db).await {
+                            Ok((_, identified)) => identified,
+                            Err(code) => {
+                                log::info!(
+                                    "Disconnected a client: {}, reason: Failed to identify",
+                                    rl_address
+                                );
+                                return Some(code);
+                            }
+                        };
+                        log::debug!("Client {} identified as {}", rl_address, identified.id);
+                        rate_limiter.set_identifier(identified.id);
+                        dispatched.lock().await.allowed = true;
+                        // Only the identified user can resume the session from here on.
+                        if let Err(err) =
+                            renew_session(&session_id, Some(identified.id), &mut session_cache)
+                                .await
+                        {
+                            log::warn!("Failed to renew session {}: {}", session_id, err);
+                        }
+                        if let Err(err) =
+                            add_presence(&identified, &connection_id, &mut session_cache).await
+                        {
+                            log::warn!("Failed to add presence of {}: {}", identified.id, err);
+                        }
+                        let online_users = match get_online_users(&mut session_cache, &db).await {
+                            Ok(users) => users,
+                            Err(err) => {
+                                log::warn!("Failed to fetch online users: {}", err);
+                                vec![]
+                            }
+                        };
+                        let res = send_payload(
+                            &mut *tx.lock().await,
+                            encoding,
+                            &compression,
+                            &Payload::Authenticated {
+                                user: identified.clone(),
+                                online_users,
+                            },
+                        )
+                        .await;
+                        if let Err(err) = res {
+                            log::error!("Could not send gateway AUTHENTICATED frame: {}", err);
+                        }
+                        user = Some(identified);
+                    }
+                    Ok(Payload::Subscribe { channel_ids }) => {
+                        log::debug!(
+                            "Client {} subscribed to {} channels",
+                            rl_address,
+                            channel_ids.len()
+                        );
+                        dispatched.lock().await.channels = Some(channel_ids.into_iter().collect());
+                    }
+                    res => {
+                        let message = rejection_reason(res);
+                        log::debug!("Invalid gateway payload from {}: {}", rl_address, message);
+                        let res = send_payload(
+                            &mut *tx.lock().await,
+                            encoding,
+                            &compression,
+                            &Payload::Error { message },
+                        )
+                        .await;
+                        if let Err(err) = res {
+                            log::error!("Could not send gateway ERROR frame: {}", err);
+                        }
+                        invalid_payloads += 1;
+                        if invalid_payloads >= conf.pandemonium.max_invalid_payloads {
+                            log::info!(
+                                "Disconnected a client: {}, reason: Sent too many invalid payloads",
+                                rl_address
+                            );
+                            return Some(GatewayCloseCode::InvalidPayload);
                         }
                     }
-                    _ => log::debug!("Unsupported Gateway message type."),
                 },
+                Err(
+                    WebSocketError::Protocol(_)
+                    | WebSocketError::Capacity(_)
+                    | WebSocketError::Utf8,
+                ) => return Some(GatewayCloseCode::InvalidPayload),
                 Err(_) => break,
             }
         }
+        // The client closed the connection itself.
+        None
     };
 
     let handle_events = async {
-        pubsub
-            .into_on_message()
-            .for_each(|msg| async {
-                match deserialize_message(msg) {
-                    Ok(msg) => {
-                        if let Err(err) = tx
-                            .lock()
-                            .await
-                            .send(WebSocketMessage::Text(
-                                serde_json::to_string(&msg).expect("Couldn't serialize payload"),
-                            ))
-                            .await
-                        {
-                            log::warn!("Failed to send payload to {}: {}", rl_address, err);
-                        }
-                    }
-                    Err(err) => log::warn!("Failed to deserialize event payload: {}", err),
+        loop {
+            let msg = match events.recv().await {
+                Ok(msg) => msg,
+                Err(RecvError::Lagged(skipped)) => {
+                    // The client can resume its session to get the events it missed.
+                    log::info!(
+                        "Disconnected a client: {}, reason: Lagged behind by {} events",
+                        rl_address,
+                        skipped
+                    );
+                    return GatewayCloseCode::Lagged;
                 }
-            })
-            .await;
+                Err(RecvError::Closed) => return GatewayCloseCode::ServerError,
+            };
+            let mut tx = tx.lock().await;
+            let mut dispatched = dispatched.lock().await;
+            // The event was already sent while resuming the session.
+            if matches!(dispatched.last_seq, Some(last) if msg.seq <= last) {
+                continue;
+            }
+            dispatched.first_seq.get_or_insert(msg.seq);
+            dispatched.last_seq = Some(msg.seq);
+            if !dispatched.is_subscribed(&msg.payload) {
+                continue;
+            }
+            if let Err(err) = send_payload(&mut tx, encoding, &compression, &msg).await {
+                log::warn!("Failed to send payload to {}: {}", rl_address, err);
+            }
+        }
     };
 
-    tokio::select! {
+    let code = tokio::select! {
         _ = check_connection(last_ping.clone()) => {
             log::info!("Dead connection with client {}", rl_address);
-            close_socket(tx, rx, CloseFrame { code: CloseCode::Error, reason: Cow::Borrowed("Client connection dead") }, rl_address).await
+            Some(GatewayCloseCode::HeartbeatTimeout)
+        }
+        code = handle_rx => {
+            if code.is_none() {
+                log::info!("Client {} disconnected", rl_address);
+            }
+            code
         }
-        _ = handle_rx => {
-            close_socket(tx, rx, CloseFrame { code: CloseCode::Error, reason: Cow::Borrowed("Client hit rate limit") }, rl_address).await;
-        },
-        _ = handle_events => {
-            close_socket(tx, rx, CloseFrame { code: CloseCode::Error, reason: Cow::Borrowed("Server Error") }, rl_address).await;
-        },
+        _ = shutdown.changed() => Some(GatewayCloseCode::Restarting),
+        code = handle_events => Some(code),
     };
+    if let Some(user) = user {
+        if let Err(err) = remove_presence(&user, &connection_id, &mut session_cache).await {
+            log::warn!("Failed to remove presence of {}: {}", user.id, err);
+        }
+    }
+    // The client already closed the connection itself otherwise.
+    if let Some(code) = code {
+        close_socket(tx, rx, code, rl_address).await;
+    }
 }
 
 async fn close_socket(
     tx: Arc<Mutex<SplitSink<WebSocketStream<TcpStream>, WebSocketMessage>>>,
     rx: SplitStream<WebSocketStream<TcpStream>>,
-    frame: CloseFrame<'_>,
+    code: GatewayCloseCode,
     rl_address: IpAddr,
 ) {
     let tx = Arc::try_unwrap(tx).expect("Couldn't obtain tx from MutexLock");
@@ -185,9 +536,78 @@ async fn close_socket(
     if let Err(err) = tx
         .reunite(rx)
         .expect("Couldn't reunite WebSocket stream")
-        .close(Some(frame))
+        .close(Some(close_frame(code)))
         .await
     {
         log::debug!("Couldn't close socket with {}: {}", rl_address, err);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use todel::models::{Payload, User};
+    use tokio_tungstenite::tungstenite::Message as WebSocketMessage;
+
+    use super::{rejection_reason, DispatchState, HEARTBEAT_INTERVAL, TIMEOUT_DURATION};
+    use crate::encoding::Encoding;
+
+    fn reason(frame: &str) -> String {
+        rejection_reason(
+            Encoding::Json.deserialize::<Payload>(&WebSocketMessage::Text(frame.to_string())),
+        )
+    }
+
+    #[test]
+    fn rejected_payloads() {
+        assert_eq!(
+            reason(r#"{"op":"IDENTIFY","d":{"token":"woo"}}"#),
+            "Already identified"
+        );
+        assert_eq!(
+            reason(r#"{"op":"RESUME","d":{"session_id":"woo","seq":1}}"#),
+            "Clients have to identify before resuming"
+        );
+        assert_eq!(reason(r#"{"op":"PONG"}"#), "Unsupported payload");
+        assert!(reason(r#"{"op":"WOO"}"#).contains("unknown variant `WOO`"));
+        assert!(reason(r#"{"op":"IDENTIFY"}"#).contains("missing field `d`"));
+        assert!(reason("woo").contains("expected value"));
+    }
+
+    #[test]
+    fn heartbeat() {
+        // Clients pinging at the advertised interval are never timed out.
+        assert!(HEARTBEAT_INTERVAL < TIMEOUT_DURATION);
+    }
+
+    #[test]
+    fn subscriptions() {
+        let typing = |channel_id| Payload::TypingStart {
+            channel_id,
+            user: User {
+                id: 1,
+                username: "woo".to_string(),
+            },
+        };
+        let channel_delete = Payload::ChannelDelete { id: 2 };
+
+        // Clients receive nothing until they identify.
+        let mut dispatched = DispatchState::default();
+        assert!(!dispatched.is_subscribed(&typing(1)));
+        assert!(!dispatched.is_subscribed(&channel_delete));
+
+        // Identified clients receive the events of every channel until they subscribe.
+        dispatched.allowed = true;
+        assert!(dispatched.is_subscribed(&typing(1)));
+        assert!(dispatched.is_subscribed(&typing(2)));
+
+        dispatched.channels = Some([1].into_iter().collect());
+        assert!(dispatched.is_subscribed(&typing(1)));
+        assert!(!dispatched.is_subscribed(&typing(2)));
+        assert!(!dispatched.is_subscribed(&Payload::MessageDelete {
+            id: 3,
+            channel_id: 2
+        }));
+        // Events which don't belong to a channel are always sent.
+        assert!(dispatched.is_subscribed(&channel_delete));
+    }
+}
@@ -0,0 +1,122 @@
+use std::time::{Duration, SystemTime};
+
+use deadpool_redis::{
+    redis::{self, RedisResult},
+    Connection,
+};
+use todel::models::{Payload, PresenceStatus, User};
+
+/// The duration a user stays online after the last ping of any of its connections.
+const PRESENCE_TTL: Duration = Duration::from_secs(60);
+
+/// The KeyDB sorted set of the ids of online users, scored by when their presence expires.
+const ONLINE_USERS_KEY: &str = "presence:online";
+
+/// Get the current Unix timestamp.
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .expect("Couldn't get current timestamp")
+        .as_secs()
+}
+
+/// Marks a connection of a user as online, notifying clients if the user just came online.
+pub async fn add_presence(
+    user: &User,
+    connection_id: &str,
+    cache: &mut Connection,
+) -> RedisResult<()> {
+    let key = format!("presence:{}", user.id);
+    let (connections,): (u64,) = redis::pipe()
+        .atomic()
+        .sadd(&key, connection_id)
+        .ignore()
+        .expire(&key, PRESENCE_TTL.as_secs() as usize)
+        .ignore()
+        .zadd(
+            ONLINE_USERS_KEY,
+            user.id.to_string(),
+            now() + PRESENCE_TTL.as_secs(),
+        )
+        .ignore()
+        .scard(&key)
+        .query_async(cache)
+        .await?;
+    if connections == 1 {
+        Payload::PresenceUpdate {
+            user: user.clone(),
+            status: PresenceStatus::Online,
+        }
+        .publish(cache)
+        .await?;
+    }
+    Ok(())
+}
+
+/// Keeps a user online for another `PRESENCE_TTL`.
+pub async fn renew_presence(user: &User, cache: &mut Connection) -> RedisResult<()> {
+    redis::pipe()
+        .atomic()
+        .expire(
+            format!("presence:{}", user.id),
+            PRESENCE_TTL.as_secs() as usize,
+        )
+        .ignore()
+        .zadd(
+            ONLINE_USERS_KEY,
+            user.id.to_string(),
+            now() + PRESENCE_TTL.as_secs(),
+        )
+        .ignore()
+        .query_async(cache)
+        .await
+}
+
+/// Get the ids of all the users which are currently online.
+pub async fn online_users(cache: &mut Connection) -> RedisResult<Vec<u128>> {
+    let now = now();
+    let (ids,): (Vec<String>,) = redis::pipe()
+        .atomic()
+        .zrembyscore(ONLINE_USERS_KEY, "-inf", now)
+        .ignore()
+        .zrangebyscore(ONLINE_USERS_KEY, format!("({}", now), "+inf")
+        .query_async(cache)
+        .await?;
+    // Users whose last connection closed are only removed from their presence set.
+    let mut pipe = redis::pipe();
+    for id in ids.iter() {
+        pipe.scard(format!("presence:{}", id));
+    }
+    let connections: Vec<u64> = pipe.query_async(cache).await?;
+    Ok(ids
+        .into_iter()
+        .zip(connections)
+        .filter(|(_, connections)| *connections > 0)
+        .filter_map(|(id, _)| id.parse().ok())
+        .collect())
+}
+
+/// Marks a connection of a user as offline, notifying clients if it was the user's last one.
+pub async fn remove_presence(
+    user: &User,
+    connection_id: &str,
+    cache: &mut Connection,
+) -> RedisResult<()> {
+    let key = format!("presence:{}", user.id);
+    let (connections,): (u64,) = redis::pipe()
+        .atomic()
+        .srem(&key, connection_id)
+        .ignore()
+        .scard(&key)
+        .query_async(cache)
+        .await?;
+    if connections == 0 {
+        Payload::PresenceUpdate {
+            user: user.clone(),
+            status: PresenceStatus::Offline,
+        }
+        .publish(cache)
+        .await?;
+    }
+    Ok(())
+}
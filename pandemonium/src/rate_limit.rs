@@ -36,6 +36,14 @@ impl RateLimiter {
         }
     }
 
+    /// Moves the RateLimiter to the bucket of another identifier
+    pub fn set_identifier<I>(&mut self, identifier: I)
+    where
+        I: Display,
+    {
+        self.key = format!("rate_limit:pandemonium:{}", identifier);
+    }
+
     /// Checks if a bucket is rate limited
     pub async fn process_rate_limit(&mut self) -> Result<(), ()> {
         let now = SystemTime::now()
@@ -0,0 +1,86 @@
+use std::time::Duration;
+
+use deadpool_redis::{
+    redis::{AsyncCommands, RedisResult},
+    Connection,
+};
+
+/// The duration a session stays resumable after its client's last ping.
+const SESSION_TTL: Duration = Duration::from_secs(120);
+
+/// The client a session belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionOwner {
+    /// A client which has not identified
+    Unauthenticated,
+    /// A client identified as the user with this ID
+    User(u128),
+}
+
+impl SessionOwner {
+    /// Whether a client identified as the user with `user_id`, if any, can resume the session.
+    ///
+    /// The events of unauthenticated sessions can be resumed by anyone since they are sent to
+    /// every client.
+    pub fn can_resume(self, user_id: Option<u128>) -> bool {
+        match self {
+            Self::Unauthenticated => true,
+            Self::User(owner) => user_id == Some(owner),
+        }
+    }
+}
+
+/// Creates a new session for an unauthenticated client and returns its ID.
+pub async fn create_session(cache: &mut Connection) -> RedisResult<String> {
+    let session_id = format!("{:032x}", rand::random::<u128>());
+    renew_session(&session_id, None, cache).await?;
+    Ok(session_id)
+}
+
+/// Keeps a session resumable for another `SESSION_TTL`, recording the ID of the user its client
+/// identified as.
+pub async fn renew_session(
+    session_id: &str,
+    user_id: Option<u128>,
+    cache: &mut Connection,
+) -> RedisResult<()> {
+    let owner = user_id.map_or_else(String::new, |id| id.to_string());
+    cache
+        .set_ex(
+            format!("session:{}", session_id),
+            owner,
+            SESSION_TTL.as_secs() as usize,
+        )
+        .await
+}
+
+/// Gets the owner of a session, `None` if it does not exist or has expired.
+pub async fn get_session_owner(
+    session_id: &str,
+    cache: &mut Connection,
+) -> RedisResult<Option<SessionOwner>> {
+    let owner: Option<String> = cache.get(format!("session:{}", session_id)).await?;
+    Ok(owner.and_then(|owner| match owner.as_str() {
+        "" => Some(SessionOwner::Unauthenticated),
+        id => id.parse().ok().map(SessionOwner::User),
+    }))
+}
+
+/// Deletes a session, making it no longer resumable.
+pub async fn delete_session(session_id: &str, cache: &mut Connection) -> RedisResult<()> {
+    cache.del(format!("session:{}", session_id)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SessionOwner;
+
+    #[test]
+    fn can_resume() {
+        assert!(SessionOwner::Unauthenticated.can_resume(None));
+        assert!(SessionOwner::Unauthenticated.can_resume(Some(1)));
+        assert!(SessionOwner::User(1).can_resume(Some(1)));
+        assert!(!SessionOwner::User(1).can_resume(Some(2)));
+        assert!(!SessionOwner::User(1).can_resume(None));
+    }
+}
@@ -1,6 +1,6 @@
 use deadpool_redis::redis::Msg;
 use std::{error::Error, fmt::Display};
-use todel::models::Payload;
+use todel::models::SequencedPayload;
 
 /// An Error that represents a Payload not being found.
 #[derive(Debug)]
@@ -15,10 +15,37 @@ impl Display for PayloadNotFound {
 impl Error for PayloadNotFound {}
 
 /// A function that simplifies deserializing a message Payload.
-pub fn deserialize_message(payload: Msg) -> Result<Payload, Box<dyn Error + Send + Sync>> {
-    Ok(serde_json::from_str::<Payload>(
+pub fn deserialize_message(payload: Msg) -> Result<SequencedPayload, Box<dyn Error + Send + Sync>> {
+    Ok(serde_json::from_str::<SequencedPayload>(
         &payload
             .get_payload::<String>()
             .map_err(|_| PayloadNotFound)?,
     )?)
 }
+
+/// A function that gets the value of a parameter from a connection's query string.
+pub fn get_query_param<'a>(query: Option<&'a str>, key: &str) -> Option<&'a str> {
+    query?
+        .split('&')
+        .filter_map(|param| param.split_once('='))
+        .find(|(k, _)| *k == key)
+        .map(|(_, value)| value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::get_query_param;
+
+    #[test]
+    fn query_param() {
+        let query = Some("encoding=msgpack&compress=zlib-stream&empty=");
+        assert_eq!(get_query_param(query, "encoding"), Some("msgpack"));
+        assert_eq!(get_query_param(query, "compress"), Some("zlib-stream"));
+        assert_eq!(get_query_param(query, "empty"), Some(""));
+        assert_eq!(get_query_param(query, "unknown"), None);
+        // Parameters without a value are ignored.
+        assert_eq!(get_query_param(Some("encoding"), "encoding"), None);
+        assert_eq!(get_query_param(Some(""), "encoding"), None);
+        assert_eq!(get_query_param(None, "encoding"), None);
+    }
+}
@@ -13,6 +13,9 @@ use std::{env, fs, path};
 #[cfg(feature = "logic")]
 use url::Url;
 
+#[cfg(feature = "logic")]
+use crate::ids::LEASED_WORKER_IDS_START;
+
 pub use effis_rate_limits::*;
 pub use oprish_rate_limits::*;
 
@@ -37,6 +40,8 @@ pub struct OprishConf {
     pub url: String,
     #[serde(default)]
     pub rate_limits: OprishRateLimits,
+    /// The worker ID used to generate IDs, leased from KeyDB if not set
+    pub worker_id: Option<u64>,
 }
 
 impl Default for OprishConf {
@@ -45,6 +50,7 @@ impl Default for OprishConf {
             url: "https://example.com".to_string(),
             message_limit: message_limit_default(),
             rate_limits: OprishRateLimits::default(),
+            worker_id: None,
         }
     }
 }
@@ -59,6 +65,12 @@ pub struct PandemoniumConf {
     pub url: String,
     #[serde(default = "pandemonium_rate_limit_default")]
     pub rate_limit: RateLimitConf,
+    /// The amount of invalid payloads a client can send before getting disconnected
+    #[serde(default = "max_invalid_payloads_default")]
+    pub max_invalid_payloads: u32,
+    /// Whether clients which did not identify receive public events
+    #[serde(default = "allow_unauthenticated_default")]
+    pub allow_unauthenticated: bool,
 }
 
 impl Default for PandemoniumConf {
@@ -66,10 +78,20 @@ impl Default for PandemoniumConf {
         Self {
             url: "https://example.com".to_string(),
             rate_limit: pandemonium_rate_limit_default(),
+            max_invalid_payloads: max_invalid_payloads_default(),
+            allow_unauthenticated: allow_unauthenticated_default(),
         }
     }
 }
 
+fn max_invalid_payloads_default() -> u32 {
+    5
+}
+
+fn allow_unauthenticated_default() -> bool {
+    true
+}
+
 fn pandemonium_rate_limit_default() -> RateLimitConf {
     RateLimitConf {
         reset_after: 10,
@@ -89,6 +111,10 @@ pub struct EffisConf {
     pub url: String,
     #[serde(default)]
     pub rate_limits: EffisRateLimits,
+    /// The worker ID used to generate IDs, leased from KeyDB if not set
+    pub worker_id: Option<u64>,
+    #[serde(default)]
+    pub storage: StorageConf,
 }
 
 fn file_size_default() -> u64 {
@@ -106,10 +132,49 @@ impl Default for EffisConf {
             url: "https://example.com".to_string(),
             attachment_file_size: attachment_file_size_default(),
             rate_limits: EffisRateLimits::default(),
+            worker_id: None,
+            storage: StorageConf::default(),
         }
     }
 }
 
+/// The storage backend Effis keeps files in.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[serde(tag = "type")]
+pub enum StorageConf {
+    /// A directory on the local filesystem
+    Local {
+        #[serde(default = "local_storage_path_default")]
+        path: String,
+    },
+    /// A bucket of an S3 compatible object storage
+    S3 {
+        endpoint: String,
+        bucket: String,
+        #[serde(default = "s3_region_default")]
+        region: String,
+        access_key: String,
+        secret_key: String,
+    },
+}
+
+impl Default for StorageConf {
+    fn default() -> Self {
+        Self::Local {
+            path: local_storage_path_default(),
+        }
+    }
+}
+
+fn local_storage_path_default() -> String {
+    "files".to_string()
+}
+
+fn s3_region_default() -> String {
+    "us-east-1".to_string()
+}
+
 /// RateLimit config data.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RateLimitConf {
@@ -197,7 +262,22 @@ impl Conf {
         if self.oprish.message_limit < 1024 {
             bail!("Message limit can not be less than 1024 characters");
         }
-        validate_rate_limit_limits!(self.oprish.rate_limits, info, message_create, rate_limits);
+        validate_rate_limit_limits!(
+            self.oprish.rate_limits,
+            info,
+            message_create,
+            message_edit,
+            message_delete,
+            get_messages,
+            channel_create,
+            channel_edit,
+            channel_delete,
+            get_channels,
+            user_create,
+            session_create,
+            typing,
+            rate_limits
+        );
         validate_rate_limit_limits!(self.pandemonium, rate_limit);
         validate_rate_limit_limits!(self.effis.rate_limits, assets, attachments, fetch_file);
 
@@ -207,6 +287,15 @@ impl Conf {
             .with_context(|| format!("Invalid pandemonium url {}", self.pandemonium.url))?;
         Url::parse(&self.effis.url)
             .with_context(|| format!("Invalid effis url {}", self.effis.url))?;
+        if let StorageConf::S3 {
+            endpoint, bucket, ..
+        } = &self.effis.storage
+        {
+            Url::parse(endpoint).with_context(|| format!("Invalid S3 endpoint {}", endpoint))?;
+            if bucket.is_empty() {
+                bail!("S3 bucket can't be empty");
+            }
+        }
 
         validate_file_sizes!(
             self.effis.file_size,
@@ -215,6 +304,21 @@ impl Conf {
             self.effis.rate_limits.attachments.file_size_limit
         );
 
+        for worker_id in [self.oprish.worker_id, self.effis.worker_id]
+            .into_iter()
+            .flatten()
+        {
+            if worker_id >= LEASED_WORKER_IDS_START {
+                bail!(
+                    "Worker ids have to be smaller than {}, the larger ones are leased from KeyDB",
+                    LEASED_WORKER_IDS_START
+                );
+            }
+        }
+        if self.oprish.worker_id.is_some() && self.oprish.worker_id == self.effis.worker_id {
+            bail!("Oprish and Effis can not share the same worker id");
+        }
+
         Ok(())
     }
 }
@@ -258,6 +362,13 @@ mod tests {
 
             [effis.rate_limits]
             attachments = { reset_after = 600, limit = 20, file_size_limit = "500MB"}
+
+            [effis.storage]
+            type = "s3"
+            endpoint = "http://127.0.0.1:9000"
+            bucket = "eludris"
+            access_key = "minioadmin"
+            secret_key = "minioadmin"
             "#;
 
         let conf_str: Conf = toml::from_str(conf_str).unwrap();
@@ -281,6 +392,7 @@ mod tests {
                     limit: 10,
                 },
                 url: "wss://foo.bar".to_string(),
+                ..Default::default()
             },
             effis: EffisConf {
                 file_size: 100_000_000,
@@ -292,6 +404,13 @@ mod tests {
                     },
                     ..Default::default()
                 },
+                storage: StorageConf::S3 {
+                    endpoint: "http://127.0.0.1:9000".to_string(),
+                    bucket: "eludris".to_string(),
+                    region: "us-east-1".to_string(),
+                    access_key: "minioadmin".to_string(),
+                    secret_key: "minioadmin".to_string(),
+                },
                 ..Default::default()
             },
         };
@@ -375,11 +494,34 @@ mod tests {
             conf.effis.rate_limits.fetch_file,
             conf.oprish.rate_limits.info,
             conf.oprish.rate_limits.message_create,
+            conf.oprish.rate_limits.message_edit,
+            conf.oprish.rate_limits.message_delete,
+            conf.oprish.rate_limits.get_messages,
+            conf.oprish.rate_limits.channel_create,
+            conf.oprish.rate_limits.channel_edit,
+            conf.oprish.rate_limits.channel_delete,
+            conf.oprish.rate_limits.get_channels,
+            conf.oprish.rate_limits.user_create,
+            conf.oprish.rate_limits.session_create,
+            conf.oprish.rate_limits.typing,
             conf.oprish.rate_limits.rate_limits
         );
 
         test_urls!(conf, oprish, pandemonium, effis);
 
+        conf.oprish.worker_id = Some(1 << 48);
+        assert!(conf.validate().is_err());
+        conf.oprish.worker_id = Some(1 << 47);
+        assert!(conf.validate().is_err());
+        conf.oprish.worker_id = Some((1 << 47) - 1);
+        assert!(conf.validate().is_ok());
+        conf.oprish.worker_id = Some(1);
+        assert!(conf.validate().is_ok());
+        conf.effis.worker_id = Some(1);
+        assert!(conf.validate().is_err());
+        conf.effis.worker_id = Some(2);
+        assert!(conf.validate().is_ok());
+
         test_file_sizes!(
             conf,
             conf.effis.file_size,
@@ -387,5 +529,18 @@ mod tests {
             conf.effis.rate_limits.assets.file_size_limit,
             conf.effis.rate_limits.attachments.file_size_limit
         );
+
+        conf.effis.storage = StorageConf::S3 {
+            endpoint: "notavalidurl".to_string(),
+            bucket: "eludris".to_string(),
+            region: "us-east-1".to_string(),
+            access_key: "minioadmin".to_string(),
+            secret_key: "minioadmin".to_string(),
+        };
+        assert!(conf.validate().is_err());
+        if let StorageConf::S3 { endpoint, .. } = &mut conf.effis.storage {
+            *endpoint = "http://127.0.0.1:9000".to_string();
+        }
+        assert!(conf.validate().is_ok());
     }
 }
@@ -9,6 +9,26 @@ pub struct OprishRateLimits {
     pub info: RateLimitConf,
     #[serde(default = "message_create_default")]
     pub message_create: RateLimitConf,
+    #[serde(default = "message_edit_default")]
+    pub message_edit: RateLimitConf,
+    #[serde(default = "message_delete_default")]
+    pub message_delete: RateLimitConf,
+    #[serde(default = "get_messages_default")]
+    pub get_messages: RateLimitConf,
+    #[serde(default = "channel_create_default")]
+    pub channel_create: RateLimitConf,
+    #[serde(default = "channel_edit_default")]
+    pub channel_edit: RateLimitConf,
+    #[serde(default = "channel_delete_default")]
+    pub channel_delete: RateLimitConf,
+    #[serde(default = "get_channels_default")]
+    pub get_channels: RateLimitConf,
+    #[serde(default = "user_create_default")]
+    pub user_create: RateLimitConf,
+    #[serde(default = "session_create_default")]
+    pub session_create: RateLimitConf,
+    #[serde(default = "typing_default")]
+    pub typing: RateLimitConf,
     #[serde(default = "rate_limits_default")]
     pub rate_limits: RateLimitConf,
 }
@@ -18,6 +38,16 @@ impl Default for OprishRateLimits {
         Self {
             info: info_default(),
             message_create: message_create_default(),
+            message_edit: message_edit_default(),
+            message_delete: message_delete_default(),
+            get_messages: get_messages_default(),
+            channel_create: channel_create_default(),
+            channel_edit: channel_edit_default(),
+            channel_delete: channel_delete_default(),
+            get_channels: get_channels_default(),
+            user_create: user_create_default(),
+            session_create: session_create_default(),
+            typing: typing_default(),
             rate_limits: rate_limits_default(),
         }
     }
@@ -37,6 +67,76 @@ fn message_create_default() -> RateLimitConf {
     }
 }
 
+fn message_edit_default() -> RateLimitConf {
+    RateLimitConf {
+        reset_after: 5,
+        limit: 5,
+    }
+}
+
+fn message_delete_default() -> RateLimitConf {
+    RateLimitConf {
+        reset_after: 5,
+        limit: 5,
+    }
+}
+
+fn get_messages_default() -> RateLimitConf {
+    RateLimitConf {
+        reset_after: 5,
+        limit: 5,
+    }
+}
+
+fn channel_create_default() -> RateLimitConf {
+    RateLimitConf {
+        reset_after: 5,
+        limit: 10,
+    }
+}
+
+fn channel_edit_default() -> RateLimitConf {
+    RateLimitConf {
+        reset_after: 5,
+        limit: 5,
+    }
+}
+
+fn channel_delete_default() -> RateLimitConf {
+    RateLimitConf {
+        reset_after: 5,
+        limit: 5,
+    }
+}
+
+fn get_channels_default() -> RateLimitConf {
+    RateLimitConf {
+        reset_after: 5,
+        limit: 5,
+    }
+}
+
+fn user_create_default() -> RateLimitConf {
+    RateLimitConf {
+        reset_after: 60,
+        limit: 2,
+    }
+}
+
+fn session_create_default() -> RateLimitConf {
+    RateLimitConf {
+        reset_after: 60,
+        limit: 5,
+    }
+}
+
+fn typing_default() -> RateLimitConf {
+    RateLimitConf {
+        reset_after: 5,
+        limit: 5,
+    }
+}
+
 fn rate_limits_default() -> RateLimitConf {
     RateLimitConf {
         reset_after: 5,
@@ -0,0 +1,71 @@
+use rocket::{
+    http::Status,
+    request::{FromRequest, Outcome, Request},
+};
+use rocket_db_pools::Connection;
+
+use super::{catchers::GuardError, Cache, DB};
+use crate::models::{
+    ErrorResponse, ErrorResponseData, ServerError, Session, UnauthorizedError, User,
+};
+
+/// The session and user of a client authenticated by the token in its `Authorization` header.
+///
+/// Tokens are validated against the database and then cached in KeyDB.
+#[derive(Debug, Clone)]
+pub struct Authenticated {
+    pub session: Session,
+    pub user: User,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Authenticated {
+    type Error = ErrorResponse;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let token = match req.headers().get_one("Authorization") {
+            Some(token) => token,
+            None => return fail(req, UnauthorizedError.to_error_response()),
+        };
+
+        let mut cache = req.guard::<Connection<Cache>>().await.succeeded();
+        if let Some(cache) = &mut cache {
+            if let Some((session, user)) = Session::get_cached(token, &mut **cache).await {
+                return Outcome::Success(Self { session, user });
+            }
+        }
+
+        let mut db = match req.guard::<Connection<DB>>().await {
+            Outcome::Success(db) => db,
+            _ => {
+                return fail(
+                    req,
+                    ServerError {
+                        error: "Failed to fetch session".to_string(),
+                    }
+                    .to_error_response(),
+                )
+            }
+        };
+        let (session, user) = match Session::validate(token, &mut db).await {
+            Ok(session) => session,
+            Err(err) => return fail(req, err),
+        };
+
+        if let Some(cache) = &mut cache {
+            session.cache(token, &user, &mut **cache).await;
+        }
+
+        Outcome::Success(Self { session, user })
+    }
+}
+
+/// Fail a request guard with an [`ErrorResponse`], which is stored for the
+/// [`catchers`](super::catchers) to respond with.
+fn fail<T>(req: &Request<'_>, err: ErrorResponse) -> Outcome<T, ErrorResponse> {
+    GuardError::store(req, &err);
+    Outcome::Failure((
+        Status::from_code(err.status).unwrap_or(Status::InternalServerError),
+        err,
+    ))
+}
@@ -0,0 +1,39 @@
+use rocket::{catch, catchers, request::Request, Catcher};
+
+use crate::models::{ErrorResponse, ErrorResponseData, ServerError, UnauthorizedError};
+
+/// The error of a failed request guard, which Rocket drops before calling a catcher.
+#[derive(Debug, Clone)]
+pub(crate) struct GuardError(Option<ErrorResponse>);
+
+impl GuardError {
+    /// Store the error of a failed request guard for the catchers to respond with.
+    pub(crate) fn store(req: &Request<'_>, err: &ErrorResponse) {
+        req.local_cache(|| GuardError(Some(err.clone())));
+    }
+
+    fn get(req: &Request<'_>) -> Option<ErrorResponse> {
+        req.local_cache(|| GuardError(None)).0.clone()
+    }
+}
+
+/// Get the catchers which respond with the [`ErrorResponse`] of a failed request guard such as
+/// [`Authenticated`](super::Authenticated).
+pub fn catchers() -> Vec<Catcher> {
+    catchers![unauthorized, internal_server_error]
+}
+
+#[catch(401)]
+fn unauthorized(req: &Request<'_>) -> ErrorResponse {
+    GuardError::get(req).unwrap_or_else(|| UnauthorizedError.to_error_response())
+}
+
+#[catch(500)]
+fn internal_server_error(req: &Request<'_>) -> ErrorResponse {
+    GuardError::get(req).unwrap_or_else(|| {
+        ServerError {
+            error: "Internal server error".to_string(),
+        }
+        .to_error_response()
+    })
+}
@@ -0,0 +1,11 @@
+use rocket_db_pools::{deadpool_redis::Pool, sqlx::MySqlPool, Database};
+
+/// The MariaDB database pool shared by the Eludris microservices.
+#[derive(Database)]
+#[database("db")]
+pub struct DB(pub MySqlPool);
+
+/// The KeyDB cache pool shared by the Eludris microservices.
+#[derive(Database)]
+#[database("cache")]
+pub struct Cache(pub Pool);
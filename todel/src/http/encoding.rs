@@ -0,0 +1,70 @@
+use rocket::{
+    data::{self, Data, FromData},
+    http::{ContentType, MediaType, Status},
+    request::Request,
+    response::{self, content::RawMsgPack, Responder},
+    serde::{
+        json::{self, Json},
+        msgpack::{self, MsgPack},
+    },
+};
+use serde::{de::DeserializeOwned, Serialize};
+
+/// A request or response body encoded as either JSON or MessagePack.
+///
+/// Request bodies are decoded according to their `Content-Type` while responses are encoded in
+/// the format preferred by the request's `Accept` header, both default to JSON.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Encoded<T>(pub T);
+
+impl<T> Encoded<T> {
+    /// Consumes the wrapper and returns the wrapped item.
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+/// The error returned when a request body could not be decoded.
+#[derive(Debug)]
+pub enum EncodingError<'r> {
+    Json(json::Error<'r>),
+    MsgPack(msgpack::Error),
+}
+
+/// Checks whether a request prefers MessagePack responses over JSON ones.
+fn prefers_msgpack(req: &Request<'_>) -> bool {
+    matches!(req.accept(), Some(accept) if accept.preferred().media_type() == &MediaType::MsgPack)
+}
+
+#[rocket::async_trait]
+impl<'r, T: DeserializeOwned> FromData<'r> for Encoded<T> {
+    type Error = EncodingError<'r>;
+
+    async fn from_data(req: &'r Request<'_>, data: Data<'r>) -> data::Outcome<'r, Self> {
+        if req.content_type() == Some(&ContentType::MsgPack) {
+            MsgPack::<T>::from_data(req, data)
+                .await
+                .map(|body| Encoded(body.into_inner()))
+                .map_failure(|(status, err)| (status, EncodingError::MsgPack(err)))
+        } else {
+            Json::<T>::from_data(req, data)
+                .await
+                .map(|body| Encoded(body.into_inner()))
+                .map_failure(|(status, err)| (status, EncodingError::Json(err)))
+        }
+    }
+}
+
+impl<'r, T: Serialize> Responder<'r, 'static> for Encoded<T> {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'static> {
+        if prefers_msgpack(req) {
+            let body = rmp_serde::to_vec_named(&self.0).map_err(|e| {
+                log::error!("Failed to serialize MessagePack response: {}", e);
+                Status::InternalServerError
+            })?;
+            RawMsgPack(body).respond_to(req)
+        } else {
+            Json(self.0).respond_to(req)
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use rocket::{
+    fairing::{self, AdHoc},
+    tokio::sync::Mutex,
+    Build, Rocket, Shutdown,
+};
+use rocket_db_pools::{deadpool_redis::Pool, Database};
+use tokio::time;
+
+use super::Cache;
+use crate::ids::{lease_new_worker_id, lease_worker_id, renew_worker_id, IDGenerator, WorkerLease};
+
+/// The duration after which a leased worker id expires if it isn't renewed.
+const WORKER_ID_TTL: Duration = Duration::from_secs(60);
+
+/// Manage an [`IDGenerator`] using the configured worker id, or one leased from KeyDB which is
+/// kept renewed in the background.
+///
+/// This requires the [`Cache`] to be attached beforehand.
+pub async fn init_id_generator(rocket: Rocket<Build>, worker_id: Option<u64>) -> fairing::Result {
+    if let Some(worker_id) = worker_id {
+        // A process restarting within the same second would otherwise generate the ids it
+        // already generated right before stopping again.
+        let subsec = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .expect("Couldn't get current timestamp")
+            .subsec_nanos();
+        time::sleep(Duration::from_secs(1) - Duration::from_nanos(subsec as u64)).await;
+        return Ok(rocket.manage(Mutex::new(IDGenerator::new(worker_id))));
+    }
+    let pool = match Cache::fetch(&rocket) {
+        Some(cache) => cache.0.clone(),
+        None => return Err(rocket),
+    };
+    let lease = match pool.get().await {
+        Ok(mut cache) => match lease_worker_id(&mut cache, WORKER_ID_TTL).await {
+            Ok(lease) => Arc::new(lease),
+            Err(err) => {
+                log::error!("Failed to lease a worker id: {}", err);
+                return Err(rocket);
+            }
+        },
+        Err(err) => {
+            log::error!("Couldn't get a cache connection: {}", err);
+            return Err(rocket);
+        }
+    };
+    let generator = IDGenerator::with_lease(lease.clone());
+    Ok(rocket
+        .manage(Mutex::new(generator))
+        .attach(AdHoc::on_liftoff("Worker ID Renewal", |rocket| {
+            let shutdown = rocket.shutdown();
+            Box::pin(async move {
+                tokio::spawn(renew_lease(pool, lease, shutdown));
+            })
+        })))
+}
+
+/// Keep a worker id lease renewed, leasing a new worker id if it gets lost.
+///
+/// Rocket is shut down if no new worker id can be leased since it can't generate ids anymore.
+async fn renew_lease(pool: Pool, lease: Arc<WorkerLease>, shutdown: Shutdown) {
+    let mut interval = time::interval(WORKER_ID_TTL / 3);
+    loop {
+        interval.tick().await;
+        let mut cache = match pool.get().await {
+            Ok(cache) => cache,
+            Err(err) => {
+                log::warn!("Couldn't get a cache connection: {}", err);
+                continue;
+            }
+        };
+        match renew_worker_id(&mut cache, &lease, WORKER_ID_TTL).await {
+            Ok(true) => {}
+            Ok(false) => {
+                log::warn!(
+                    "Lost the lease on worker id {}, leasing a new one",
+                    lease.worker_id()
+                );
+                if let Err(err) = lease_new_worker_id(&mut cache, &lease, WORKER_ID_TTL).await {
+                    log::error!("Failed to lease a new worker id, shutting down: {}", err);
+                    shutdown.notify();
+                    break;
+                }
+            }
+            Err(err) => log::warn!("Failed to renew worker id {}: {}", lease.worker_id(), err),
+        }
+    }
+}
@@ -1,5 +1,16 @@
+mod authenticated;
+mod catchers;
 mod client_ip;
+mod databases;
+mod encoding;
+mod id_generator;
 mod response;
+mod snowflake;
 
+pub use authenticated::Authenticated;
+pub use catchers::catchers;
 pub use client_ip::ClientIP;
+pub use databases::{Cache, DB};
+pub use encoding::*;
+pub use id_generator::init_id_generator;
 pub use response::*;
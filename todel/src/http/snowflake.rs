@@ -0,0 +1,13 @@
+use std::num::ParseIntError;
+
+use rocket::request::FromParam;
+
+use crate::ids::Snowflake;
+
+impl<'a> FromParam<'a> for Snowflake {
+    type Error = ParseIntError;
+
+    fn from_param(param: &'a str) -> Result<Self, Self::Error> {
+        param.parse()
+    }
+}
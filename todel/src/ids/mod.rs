@@ -1,13 +1,113 @@
 //! A simple collection of ID related utilities.
 
-use std::time::{Duration, SystemTime};
+use std::{
+    fmt::Display,
+    num::ParseIntError,
+    str::FromStr,
+    time::{Duration, SystemTime},
+};
+#[cfg(feature = "logic")]
+use std::{
+    sync::{Arc, Mutex as StdMutex, MutexGuard},
+    time::Instant,
+};
 
+#[cfg(feature = "logic")]
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+#[cfg(feature = "logic")]
+use deadpool_redis::redis::{self, AsyncCommands, RedisResult};
+use serde_with::{DeserializeFromStr, SerializeDisplay};
+
+/// The number of seconds between the Unix epoch and the Eludris epoch.
+pub const ELUDRIS_EPOCH_SECS: u64 = 1_650_000_000;
+
+#[cfg(feature = "logic")]
 lazy_static! {
     pub static ref ELUDRIS_EPOCH: SystemTime =
-        SystemTime::UNIX_EPOCH + Duration::from_secs(1_650_000_000);
+        SystemTime::UNIX_EPOCH + Duration::from_secs(ELUDRIS_EPOCH_SECS);
+}
+
+/// A typed Eludris ID which is made up of a 64 bit timestamp, a 48 bit instance ID and a 16 bit
+/// sequence.
+///
+/// Snowflakes are (de)serialised as decimal strings and are ordered by their creation time.
+///
+/// ## Example
+///
+/// ```rust
+/// use todel::ids::Snowflake;
+///
+/// let snowflake: Snowflake = "481035745210123977490497568".parse().unwrap();
+///
+/// assert_eq!(snowflake.timestamp(), 26_077_000);
+/// assert_eq!(snowflake.instance_id(), 1);
+/// assert_eq!(snowflake.sequence(), 32);
+/// ```
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, SerializeDisplay, DeserializeFromStr,
+)]
+pub struct Snowflake(u128);
+
+impl Snowflake {
+    /// Create a new Snowflake from its raw value.
+    pub fn new(id: u128) -> Self {
+        Self(id)
+    }
+
+    /// Get the raw value of the Snowflake.
+    pub fn get(&self) -> u128 {
+        self.0
+    }
+
+    /// Get the number of seconds between the Eludris epoch and the Snowflake's creation.
+    pub fn timestamp(&self) -> u64 {
+        (self.0 >> 64) as u64
+    }
+
+    /// Get the time at which the Snowflake was created.
+    pub fn created_at(&self) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(ELUDRIS_EPOCH_SECS + self.timestamp())
+    }
+
+    /// Get the ID of the instance which created the Snowflake.
+    pub fn instance_id(&self) -> u64 {
+        ((self.0 >> 16) & 0xFFFFFFFFFFFF) as u64
+    }
+
+    /// Get the Snowflake's sequence.
+    pub fn sequence(&self) -> u16 {
+        (self.0 & 0xFFFF) as u16
+    }
+}
+
+impl From<u128> for Snowflake {
+    fn from(id: u128) -> Self {
+        Self(id)
+    }
+}
+
+impl From<Snowflake> for u128 {
+    fn from(snowflake: Snowflake) -> Self {
+        snowflake.0
+    }
+}
+
+impl Display for Snowflake {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl FromStr for Snowflake {
+    type Err = ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self(s.parse()?))
+    }
 }
 
 /// Generate an instance id
+#[cfg(feature = "logic")]
 pub fn generate_instance_id() -> u64 {
     // This is just a 48 bit Unix timestamp
     SystemTime::now()
@@ -17,8 +117,49 @@ pub fn generate_instance_id() -> u64 {
         & 0xFFFFFFFFFFFF
 }
 
+/// The error returned when an [`IDGenerator`] can not safely generate a new ID.
+#[cfg(feature = "logic")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IDGenerationError {
+    /// The system clock moved backwards by the provided number of seconds since the last ID was
+    /// generated.
+    ClockMovedBackwards(u64),
+    /// All the sequence numbers for the current second have already been used.
+    SequenceExhausted,
+    /// The lease on the generator's worker ID ran out, so another process may be using it.
+    LeaseLost,
+}
+
+#[cfg(feature = "logic")]
+impl Display for IDGenerationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ClockMovedBackwards(seconds) => {
+                write!(f, "Clock moved backwards by {} seconds", seconds)
+            }
+            Self::SequenceExhausted => write!(f, "Exhausted the ID sequence for this second"),
+            Self::LeaseLost => write!(f, "Lost the lease on the worker id"),
+        }
+    }
+}
+
+#[cfg(feature = "logic")]
+impl std::error::Error for IDGenerationError {}
+
+/// Get the number of seconds since the Eludris epoch.
+#[cfg(feature = "logic")]
+fn current_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(*ELUDRIS_EPOCH)
+        .expect("Couldn't get current timestamp")
+        .as_secs()
+}
+
 /// An abstraction for generating spec-compliant IDs and handling incrementing them
 ///
+/// The sequence is reset every second, generating an ID fails instead of risking a collision
+/// when the clock moves backwards or when more than 65535 IDs are requested within one second.
+///
 /// ## Example
 ///
 /// ```rust
@@ -27,76 +168,366 @@ pub fn generate_instance_id() -> u64 {
 /// let instance_id = generate_instance_id(); // This is ideally fetched from a database.
 /// let mut generator = IDGenerator::new(instance_id); // Create a new ID generator with your instance ID.
 ///
-/// generator.generate_id(); // Generate an ID which also increments the sequence.
+/// generator.generate_id().unwrap(); // Generate an ID which also increments the sequence.
 /// ```
+#[cfg(feature = "logic")]
 #[derive(Debug, Clone)]
 pub struct IDGenerator {
     instance_id: u64,
     sequence: u16,
+    last_timestamp: u64,
+    clock: fn() -> u64,
+    lease: Option<Arc<WorkerLease>>,
 }
 
+#[cfg(feature = "logic")]
 impl IDGenerator {
     /// Create a new IDGenerator from an instance ID.
     pub fn new(instance_id: u64) -> Self {
         Self {
-            instance_id,
+            instance_id: instance_id & 0xFFFFFFFFFFFF,
             sequence: 0,
+            last_timestamp: 0,
+            clock: current_timestamp,
+            lease: None,
+        }
+    }
+
+    /// Create a new IDGenerator from a leased worker ID, it stops generating IDs once the lease
+    /// runs out.
+    pub fn with_lease(lease: Arc<WorkerLease>) -> Self {
+        Self {
+            lease: Some(lease.clone()),
+            ..Self::new(lease.worker_id())
         }
     }
 
     /// Generate a new ID and handle incrementing the sequence
-    pub fn generate_id(&mut self) -> u128 {
+    pub fn generate_id(&mut self) -> Result<u128, IDGenerationError> {
+        let instance_id = match &self.lease {
+            Some(lease) => lease.held_worker_id().ok_or(IDGenerationError::LeaseLost)?,
+            None => self.instance_id,
+        };
+        let timestamp = (self.clock)();
+        if timestamp < self.last_timestamp {
+            return Err(IDGenerationError::ClockMovedBackwards(
+                self.last_timestamp - timestamp,
+            ));
+        }
+        if timestamp > self.last_timestamp {
+            self.last_timestamp = timestamp;
+            self.sequence = 0;
+        }
         if self.sequence == u16::MAX {
-            self.sequence = 0
-        } else {
-            self.sequence += 1;
+            return Err(IDGenerationError::SequenceExhausted);
+        }
+        self.sequence += 1;
+        Ok((timestamp as u128) << 64 | (instance_id as u128) << 16 | self.sequence as u128)
+    }
+}
+
+/// The KeyDB key which holds the last leased worker ID.
+#[cfg(feature = "logic")]
+const WORKER_ID_COUNTER_KEY: &str = "worker_id:counter";
+
+/// The first worker ID handed out by [`lease_worker_id`].
+///
+/// Worker IDs below this are reserved for the ones set in the config so that a leased worker ID
+/// never matches a configured one.
+pub const LEASED_WORKER_IDS_START: u64 = 1 << 47;
+
+/// A worker ID leased from KeyDB.
+///
+/// The lease is only known to be held until its TTL runs out after the last successful renewal,
+/// another process may lease the same worker ID afterwards.
+#[cfg(feature = "logic")]
+#[derive(Debug)]
+pub struct WorkerLease {
+    /// A random value identifying this lease, stored as the value of its key
+    token: String,
+    state: StdMutex<LeaseState>,
+}
+
+#[cfg(feature = "logic")]
+#[derive(Debug)]
+struct LeaseState {
+    worker_id: u64,
+    held_until: Option<Instant>,
+}
+
+#[cfg(feature = "logic")]
+impl WorkerLease {
+    fn new(worker_id: u64, token: String) -> Self {
+        Self {
+            token,
+            state: StdMutex::new(LeaseState {
+                worker_id,
+                held_until: None,
+            }),
         }
-        (SystemTime::now()
-            .duration_since(*ELUDRIS_EPOCH)
-            .expect("Couldn't get current timestamp")
-            .as_secs() as u128)
-            << 64
-            | (self.instance_id as u128) << 16
-            | self.sequence as u128
+    }
+
+    fn state(&self) -> MutexGuard<'_, LeaseState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Get the leased worker ID.
+    pub fn worker_id(&self) -> u64 {
+        self.state().worker_id
+    }
+
+    /// Get the leased worker ID if the lease is still held.
+    pub fn held_worker_id(&self) -> Option<u64> {
+        let state = self.state();
+        match state.held_until {
+            Some(held_until) if Instant::now() < held_until => Some(state.worker_id),
+            _ => None,
+        }
+    }
+
+    /// Mark `worker_id` as held for `ttl` after `since`, the moment the command which set its
+    /// expiry was sent.
+    fn extend(&self, worker_id: u64, since: Instant, ttl: Duration) {
+        let mut state = self.state();
+        state.worker_id = worker_id;
+        state.held_until = Some(since + ttl);
+    }
+
+    /// Mark the lease as lost.
+    fn lose(&self) {
+        self.state().held_until = None;
     }
 }
 
+/// Lease a worker ID from KeyDB to use as an [`IDGenerator`]'s instance ID.
+///
+/// The lease expires after `ttl` unless it's renewed using [`renew_worker_id`], this makes sure
+/// that no two running processes get the same worker ID.
+#[cfg(feature = "logic")]
+pub async fn lease_worker_id<C: AsyncCommands>(
+    cache: &mut C,
+    ttl: Duration,
+) -> RedisResult<WorkerLease> {
+    let mut token = [0; 16];
+    OsRng.fill_bytes(&mut token);
+    let lease = WorkerLease::new(0, token.iter().map(|b| format!("{:02x}", b)).collect());
+    lease_new_worker_id(cache, &lease, ttl).await?;
+    Ok(lease)
+}
+
+/// Lease a new worker ID for a lease which was lost, returning the new worker ID.
+#[cfg(feature = "logic")]
+pub async fn lease_new_worker_id<C: AsyncCommands>(
+    cache: &mut C,
+    lease: &WorkerLease,
+    ttl: Duration,
+) -> RedisResult<u64> {
+    loop {
+        let worker_id = LEASED_WORKER_IDS_START
+            | cache.incr::<_, _, u64>(WORKER_ID_COUNTER_KEY, 1).await?
+                & (LEASED_WORKER_IDS_START - 1);
+        let sent_at = Instant::now();
+        let leased: Option<String> = redis::cmd("SET")
+            .arg(format!("worker_id:{}", worker_id))
+            .arg(&lease.token)
+            .arg("NX")
+            .arg("PX")
+            .arg(ttl.as_millis() as u64)
+            .query_async(cache)
+            .await?;
+        if leased.is_some() {
+            lease.extend(worker_id, sent_at, ttl);
+            log::info!("Leased worker id {}", worker_id);
+            return Ok(worker_id);
+        }
+    }
+}
+
+/// Renew a worker ID lease obtained with [`lease_worker_id`].
+///
+/// The lease is only renewed if nothing else took it over, `false` is returned and the lease is
+/// marked as lost otherwise.
+#[cfg(feature = "logic")]
+pub async fn renew_worker_id<C: AsyncCommands>(
+    cache: &mut C,
+    lease: &WorkerLease,
+    ttl: Duration,
+) -> RedisResult<bool> {
+    lazy_static! {
+        static ref RENEW_SCRIPT: redis::Script = redis::Script::new(
+            r#"
+if redis.call("GET", KEYS[1]) == ARGV[1] then
+    return redis.call("PEXPIRE", KEYS[1], ARGV[2])
+end
+return 0
+            "#,
+        );
+    }
+
+    let worker_id = lease.worker_id();
+    let sent_at = Instant::now();
+    let renewed: bool = RENEW_SCRIPT
+        .key(format!("worker_id:{}", worker_id))
+        .arg(&lease.token)
+        .arg(ttl.as_millis() as u64)
+        .invoke_async(cache)
+        .await?;
+    if renewed {
+        lease.extend(worker_id, sent_at, ttl);
+    } else {
+        lease.lose();
+    }
+    Ok(renewed)
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{generate_instance_id, IDGenerator};
+    use std::time::{Duration, SystemTime};
+    #[cfg(feature = "logic")]
+    use std::{sync::Arc, time::Instant};
+
+    use super::Snowflake;
+    #[cfg(feature = "logic")]
+    use super::{generate_instance_id, IDGenerationError, IDGenerator, WorkerLease};
+
+    #[test]
+    fn snowflake() {
+        let snowflake = Snowflake::new(5 << 64 | 0xABCDEF << 16 | 42);
 
+        assert_eq!(snowflake.timestamp(), 5);
+        assert_eq!(
+            snowflake.created_at(),
+            SystemTime::UNIX_EPOCH + Duration::from_secs(1_650_000_005)
+        );
+        assert_eq!(snowflake.instance_id(), 0xABCDEF);
+        assert_eq!(snowflake.sequence(), 42);
+
+        let id = snowflake.to_string();
+        assert_eq!(id.parse::<Snowflake>().unwrap(), snowflake);
+        assert!("nope".parse::<Snowflake>().is_err());
+
+        let serialized = serde_json::to_string(&snowflake).unwrap();
+        assert_eq!(serialized, format!("\"{}\"", id));
+        assert_eq!(
+            serde_json::from_str::<Snowflake>(&serialized).unwrap(),
+            snowflake
+        );
+
+        assert!(Snowflake::new(1 << 64) > Snowflake::new(u16::MAX as u128));
+    }
+
+    #[cfg(feature = "logic")]
+    #[test]
+    fn snowflake_from_generator() {
+        let instance_id = generate_instance_id();
+        let mut generator = IDGenerator::new(instance_id);
+
+        let before = SystemTime::now() - Duration::from_secs(1);
+        let snowflake = Snowflake::from(generator.generate_id().unwrap());
+        assert!(snowflake.created_at() >= before);
+        assert_eq!(snowflake.instance_id(), instance_id);
+        assert_eq!(snowflake.sequence(), 1);
+    }
+
+    #[cfg(feature = "logic")]
     #[test]
     fn id_generator() {
         let instance_id = generate_instance_id();
         let mut generator = IDGenerator::new(instance_id);
 
-        let id = generator.generate_id();
+        let id = generator.generate_id().unwrap();
         assert_eq!(id & 0xFFFF, 1);
         assert_eq!((id & 0xFFFFFFFFFFFF0000) >> 16, instance_id as u128);
 
-        let id = generator.generate_id();
+        let id = generator.generate_id().unwrap();
         assert_eq!(id & 0xFFFF, 2);
         assert_eq!((id & 0xFFFFFFFFFFFF0000) >> 16, instance_id as u128);
     }
 
+    #[cfg(feature = "logic")]
     #[test]
     fn id_generator_overflow() {
+        fn clock() -> u64 {
+            42
+        }
+
         let instance_id = generate_instance_id();
         let mut generator = IDGenerator {
             instance_id,
             sequence: u16::MAX - 1,
+            last_timestamp: 42,
+            clock,
+            lease: None,
         };
 
-        let id = generator.generate_id();
+        let id = generator.generate_id().unwrap();
         assert_eq!(id & 0xFFFF, u16::MAX as u128);
         assert_eq!((id & 0xFFFFFFFFFFFF0000) >> 16, instance_id as u128);
 
-        let id = generator.generate_id();
-        assert_eq!(id & 0xFFFF, 0);
-        assert_eq!((id & 0xFFFFFFFFFFFF0000) >> 16, instance_id as u128);
+        assert_eq!(
+            generator.generate_id(),
+            Err(IDGenerationError::SequenceExhausted)
+        );
 
-        let id = generator.generate_id();
+        // The sequence gets reset once the clock ticks.
+        generator.clock = || 43;
+        let id = generator.generate_id().unwrap();
+        assert_eq!(id >> 64, 43);
         assert_eq!(id & 0xFFFF, 1);
         assert_eq!((id & 0xFFFFFFFFFFFF0000) >> 16, instance_id as u128);
     }
+
+    #[cfg(feature = "logic")]
+    #[test]
+    fn id_generator_clock_skew() {
+        let instance_id = generate_instance_id();
+        let mut generator = IDGenerator {
+            instance_id,
+            sequence: 0,
+            last_timestamp: 0,
+            clock: || 100,
+            lease: None,
+        };
+
+        let id = generator.generate_id().unwrap();
+        assert_eq!(id >> 64, 100);
+
+        generator.clock = || 97;
+        assert_eq!(
+            generator.generate_id(),
+            Err(IDGenerationError::ClockMovedBackwards(3))
+        );
+
+        // The generator recovers once the clock catches up.
+        generator.clock = || 100;
+        let id = generator.generate_id().unwrap();
+        assert_eq!(id >> 64, 100);
+        assert_eq!(id & 0xFFFF, 2);
+    }
+
+    #[cfg(feature = "logic")]
+    #[test]
+    fn id_generator_lease() {
+        let lease = Arc::new(WorkerLease::new(42, "woo".to_string()));
+        let mut generator = IDGenerator::with_lease(lease.clone());
+        assert_eq!(generator.generate_id(), Err(IDGenerationError::LeaseLost));
+
+        lease.extend(42, Instant::now(), Duration::from_secs(60));
+        let id = generator.generate_id().unwrap();
+        assert_eq!((id & 0xFFFFFFFFFFFF0000) >> 16, 42);
+
+        // A lease whose TTL ran out may have been taken over by another process.
+        lease.extend(42, Instant::now(), Duration::ZERO);
+        assert_eq!(generator.generate_id(), Err(IDGenerationError::LeaseLost));
+
+        lease.extend(42, Instant::now(), Duration::from_secs(60));
+        assert!(generator.generate_id().is_ok());
+        lease.lose();
+        assert_eq!(generator.generate_id(), Err(IDGenerationError::LeaseLost));
+
+        // The generator picks up the new worker ID of a lost lease once it's leased again.
+        lease.extend(43, Instant::now(), Duration::from_secs(60));
+        let id = generator.generate_id().unwrap();
+        assert_eq!((id & 0xFFFFFFFFFFFF0000) >> 16, 43);
+    }
 }
@@ -7,8 +7,11 @@ extern crate lazy_static;
 pub mod conf;
 #[cfg(feature = "http")]
 pub mod http;
-#[cfg(feature = "logic")]
 pub mod ids;
+#[cfg(feature = "http")]
+pub mod metadata;
 pub mod models;
+#[cfg(feature = "storage")]
+pub mod storage;
 
 pub use conf::Conf;
@@ -0,0 +1,68 @@
+/// The largest EXIF metadata whose orientation is read, larger metadata is dropped without
+/// reading it to keep the memory used by stripping bounded.
+pub(super) const MAX_LENGTH: u64 = 65536;
+/// The TIFF tag of an image's orientation.
+const ORIENTATION_TAG: u16 = 0x0112;
+/// The TIFF type of unsigned 16 bit integers.
+const SHORT_TYPE: u16 = 3;
+
+/// Read a 16 bit integer with the byte order of a TIFF header.
+fn read_u16(data: &[u8], offset: usize, big_endian: bool) -> Option<u16> {
+    let bytes = data.get(offset..offset + 2)?.try_into().ok()?;
+    Some(if big_endian {
+        u16::from_be_bytes(bytes)
+    } else {
+        u16::from_le_bytes(bytes)
+    })
+}
+
+/// Read a 32 bit integer with the byte order of a TIFF header.
+fn read_u32(data: &[u8], offset: usize, big_endian: bool) -> Option<u32> {
+    let bytes = data.get(offset..offset + 4)?.try_into().ok()?;
+    Some(if big_endian {
+        u32::from_be_bytes(bytes)
+    } else {
+        u32::from_le_bytes(bytes)
+    })
+}
+
+/// Get the orientation stored in EXIF data, which may start with the `Exif\0\0` identifier.
+///
+/// This returns `None` if the data is malformed or does not contain an orientation.
+pub(super) fn get_orientation(data: &[u8]) -> Option<u16> {
+    let tiff = data.strip_prefix(b"Exif\0\0").unwrap_or(data);
+    let big_endian = match tiff.get(..4)? {
+        b"MM\0\x2a" => true,
+        b"II\x2a\0" => false,
+        _ => return None,
+    };
+    let ifd = read_u32(tiff, 4, big_endian)? as usize;
+    let entries = read_u16(tiff, ifd, big_endian)? as usize;
+    (0..entries)
+        .map(|i| ifd + 2 + i * 12)
+        .find(|&entry| read_u16(tiff, entry, big_endian) == Some(ORIENTATION_TAG))
+        .and_then(|entry| {
+            if read_u16(tiff, entry + 2, big_endian)? != SHORT_TYPE {
+                return None;
+            }
+            read_u16(tiff, entry + 8, big_endian)
+        })
+        .filter(|orientation| (1..=8).contains(orientation))
+}
+
+/// Build the TIFF data of EXIF metadata only holding an orientation.
+pub(super) fn with_orientation(orientation: u16) -> Vec<u8> {
+    let mut tiff = Vec::with_capacity(26);
+    tiff.extend_from_slice(b"MM\0\x2a");
+    // The offset of the first and only IFD, right after the header.
+    tiff.extend_from_slice(&8u32.to_be_bytes());
+    tiff.extend_from_slice(&1u16.to_be_bytes());
+    tiff.extend_from_slice(&ORIENTATION_TAG.to_be_bytes());
+    tiff.extend_from_slice(&SHORT_TYPE.to_be_bytes());
+    tiff.extend_from_slice(&1u32.to_be_bytes());
+    tiff.extend_from_slice(&orientation.to_be_bytes());
+    tiff.extend_from_slice(&[0, 0]);
+    // There is no next IFD.
+    tiff.extend_from_slice(&0u32.to_be_bytes());
+    tiff
+}
@@ -0,0 +1,123 @@
+use std::io::{self, Read, Write};
+
+use anyhow::{bail, Context};
+
+use super::{copy_exact, read_full};
+
+/// The byte introducing an extension block.
+const EXTENSION: u8 = 0x21;
+/// The byte introducing an image descriptor.
+const IMAGE: u8 = 0x2C;
+/// The byte ending the GIF.
+const TRAILER: u8 = 0x3B;
+/// The label of graphic control extensions, which hold frame delays and transparency.
+const GRAPHIC_CONTROL: u8 = 0xF9;
+/// The label of plain text extensions, which are rendered as part of the image.
+const PLAIN_TEXT: u8 = 0x01;
+/// The label of application extensions.
+const APPLICATION: u8 = 0xFF;
+/// The application extensions controlling how animations loop.
+const LOOP_APPLICATIONS: &[&[u8]] = &[b"NETSCAPE2.0", b"ANIMEXTS1.0"];
+
+/// Copy the data sub-blocks at the start of the input to the output, including their terminator.
+fn copy_sub_blocks<R: Read, W: Write>(input: &mut R, output: &mut W) -> Result<(), anyhow::Error> {
+    let mut size = [0; 1];
+    loop {
+        input
+            .read_exact(&mut size)
+            .context("Truncated GIF data sub-block")?;
+        output.write_all(&size)?;
+        if size[0] == 0 {
+            return Ok(());
+        }
+        copy_exact(input, output, size[0] as u64).context("Truncated GIF data sub-block")?;
+    }
+}
+
+/// Get the length of a color table from the packed fields byte it is described in.
+fn color_table_length(packed: u8) -> u64 {
+    if packed & 0x80 != 0 {
+        3 << ((packed & 0x07) + 1)
+    } else {
+        0
+    }
+}
+
+/// Strip comments and every application extension besides the ones used to loop animations.
+pub(super) fn strip<R: Read, W: Write>(input: &mut R, output: &mut W) -> Result<(), anyhow::Error> {
+    // The header and logical screen descriptor.
+    let mut header = [0; 13];
+    if !read_full(input, &mut header)? {
+        bail!("Truncated GIF logical screen descriptor");
+    }
+    if !matches!(&header[..6], b"GIF87a" | b"GIF89a") {
+        bail!("Missing GIF signature");
+    }
+    output.write_all(&header)?;
+    copy_exact(input, output, color_table_length(header[10]))
+        .context("Truncated GIF global color table")?;
+
+    let mut block = [0; 1];
+    loop {
+        input
+            .read_exact(&mut block)
+            .context("Missing GIF trailer")?;
+        match block[0] {
+            EXTENSION => {
+                let mut label = [0; 1];
+                input
+                    .read_exact(&mut label)
+                    .context("Truncated GIF extension")?;
+                // Application extensions are identified by their first sub-block.
+                let mut first = vec![];
+                if label[0] == APPLICATION {
+                    let mut size = [0; 1];
+                    input
+                        .read_exact(&mut size)
+                        .context("Truncated GIF extension")?;
+                    first.resize(size[0] as usize + 1, 0);
+                    first[0] = size[0];
+                    input
+                        .read_exact(&mut first[1..])
+                        .context("Truncated GIF extension")?;
+                }
+                let kept = match label[0] {
+                    GRAPHIC_CONTROL | PLAIN_TEXT => true,
+                    APPLICATION => matches!(
+                        first.get(1..12),
+                        Some(application) if LOOP_APPLICATIONS.contains(&application)
+                    ),
+                    _ => false,
+                };
+                let terminated = first.first() == Some(&0);
+                if kept {
+                    output.write_all(&[EXTENSION, label[0]])?;
+                    output.write_all(&first)?;
+                    if !terminated {
+                        copy_sub_blocks(input, output)?;
+                    }
+                } else if !terminated {
+                    copy_sub_blocks(input, &mut io::sink())?;
+                }
+            }
+            IMAGE => {
+                // The image descriptor, local color table and LZW minimum code size come before
+                // the image data.
+                let mut descriptor = [0; 9];
+                input
+                    .read_exact(&mut descriptor)
+                    .context("Truncated GIF image descriptor")?;
+                output.write_all(&block)?;
+                output.write_all(&descriptor)?;
+                copy_exact(input, output, color_table_length(descriptor[8]) + 1)
+                    .context("Truncated GIF image descriptor")?;
+                copy_sub_blocks(input, output)?;
+            }
+            TRAILER => {
+                output.write_all(&block)?;
+                return Ok(());
+            }
+            block => bail!("Invalid GIF block {:#x}", block),
+        }
+    }
+}
@@ -0,0 +1,130 @@
+use std::io::{self, Read, Seek, Write};
+
+use anyhow::{bail, Context};
+
+use super::{exif, read_full};
+
+/// The start of image marker.
+const SOI: u8 = 0xD8;
+/// The start of scan marker, entropy coded data follows it.
+const SOS: u8 = 0xDA;
+/// The end of image marker.
+const EOI: u8 = 0xD9;
+/// The JFIF application segment marker.
+const APP0: u8 = 0xE0;
+/// The EXIF and XMP application segment marker.
+const APP1: u8 = 0xE1;
+/// The ICC profile application segment marker.
+const APP2: u8 = 0xE2;
+/// The Adobe application segment marker, it holds the color transform of the image.
+const APP14: u8 = 0xEE;
+/// The comment marker.
+const COM: u8 = 0xFE;
+
+/// Whether a segment is needed to display the image.
+fn is_kept(marker: u8) -> bool {
+    match marker {
+        APP0 | APP2 | APP14 => true,
+        APP1..=0xEF | COM => false,
+        _ => true,
+    }
+}
+
+/// Strip every application segment besides JFIF, ICC profile and Adobe ones along with all
+/// comments.
+pub(super) fn strip<R: Read + Seek, W: Write>(
+    input: &mut R,
+    output: &mut W,
+) -> Result<(), anyhow::Error> {
+    let orientation = copy_segments(input, &mut io::sink(), None)?;
+    input.rewind()?;
+    copy_segments(
+        input,
+        output,
+        orientation.filter(|&orientation| orientation != 1),
+    )?;
+    Ok(())
+}
+
+/// Copy the segments needed to display the image to `output`, adding an EXIF segment holding
+/// `orientation` after the JFIF one.
+///
+/// This returns the orientation stored in the EXIF metadata of the input.
+fn copy_segments<R: Read, W: Write>(
+    input: &mut R,
+    output: &mut W,
+    mut orientation: Option<u16>,
+) -> Result<Option<u16>, anyhow::Error> {
+    let mut soi = [0; 2];
+    if !read_full(input, &mut soi)? || soi != [0xFF, SOI] {
+        bail!("Missing JPEG start of image marker");
+    }
+    output.write_all(&soi)?;
+
+    let mut found = None;
+    let mut byte = [0; 1];
+    let mut segment = vec![];
+    loop {
+        input
+            .read_exact(&mut byte)
+            .context("Truncated JPEG marker")?;
+        if byte[0] != 0xFF {
+            bail!("Invalid JPEG marker {:#x}", byte[0]);
+        }
+        // Markers can be padded with any amount of fill bytes.
+        let marker = loop {
+            input
+                .read_exact(&mut byte)
+                .context("Truncated JPEG marker")?;
+            if byte[0] != 0xFF {
+                break byte[0];
+            }
+        };
+        // JFIF requires its segment to come first.
+        if marker != APP0 {
+            if let Some(orientation) = orientation.take() {
+                let exif = exif::with_orientation(orientation);
+                output.write_all(&[0xFF, APP1])?;
+                output.write_all(&(exif.len() as u16 + 8).to_be_bytes())?;
+                output.write_all(b"Exif\0\0")?;
+                output.write_all(&exif)?;
+            }
+        }
+        match marker {
+            // The entropy coded data is copied as is since everything needed to display the
+            // image is located before it.
+            SOS | EOI => {
+                output.write_all(&[0xFF, marker])?;
+                io::copy(input, output)?;
+                return Ok(found);
+            }
+            // Standalone markers have no length.
+            0x01 | 0xD0..=0xD7 => {
+                output.write_all(&[0xFF, marker])?;
+                continue;
+            }
+            _ => {}
+        }
+        let mut length = [0; 2];
+        input
+            .read_exact(&mut length)
+            .context("Truncated JPEG segment")?;
+        let data_length = match u16::from_be_bytes(length) {
+            length @ 2.. => length as usize - 2,
+            _ => bail!("Invalid JPEG segment length"),
+        };
+        // Segments are at most 64KiB long so they can be read as a whole.
+        segment.resize(data_length, 0);
+        input
+            .read_exact(&mut segment)
+            .context("Truncated JPEG segment")?;
+        if marker == APP1 && segment.starts_with(b"Exif\0\0") {
+            found = found.or_else(|| exif::get_orientation(&segment));
+        }
+        if is_kept(marker) {
+            output.write_all(&[0xFF, marker])?;
+            output.write_all(&length)?;
+            output.write_all(&segment)?;
+        }
+    }
+}
@@ -0,0 +1,191 @@
+//! Stripping of the metadata of uploaded images, such as the location a photo was taken at.
+//!
+//! Images are never re-encoded, only the parts which are not needed to display them are dropped.
+//! The only metadata which is kept is the EXIF orientation, as images would otherwise be displayed
+//! rotated.
+//!
+//! Images are streamed from their input to their output so that stripping them never requires
+//! holding more than a single segment or chunk header in memory.
+
+mod exif;
+mod gif;
+mod jpeg;
+mod png;
+mod webp;
+
+use std::io::{self, Read, Seek, Write};
+
+use anyhow::bail;
+
+/// Strip all the metadata of an image of a supported content type, writing the stripped image to
+/// `output`.
+///
+/// Inputs may be read more than once, which is why they have to be seekable.
+pub fn strip_metadata<R: Read + Seek, W: Write>(
+    content_type: &str,
+    input: &mut R,
+    output: &mut W,
+) -> Result<(), anyhow::Error> {
+    match content_type {
+        "image/gif" => gif::strip(input, output),
+        "image/jpeg" => jpeg::strip(input, output),
+        "image/png" => png::strip(input, output),
+        "image/webp" => webp::strip(input, output),
+        _ => bail!("Unsupported content type {}", content_type),
+    }
+}
+
+/// Copy `length` bytes of the input to the output, failing if the input ends before that.
+fn copy_exact<R: Read, W: Write>(input: &mut R, output: &mut W, length: u64) -> io::Result<()> {
+    if io::copy(&mut input.by_ref().take(length), output)? < length {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(())
+}
+
+/// Skip `length` bytes of the input, failing if the input ends before that.
+fn skip_exact<R: Read>(input: &mut R, length: u64) -> io::Result<()> {
+    copy_exact(input, &mut io::sink(), length)
+}
+
+/// Fill `buf` from the input, returning `false` if the input ends before that.
+fn read_full<R: Read>(input: &mut R, buf: &mut [u8]) -> io::Result<bool> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match input.read(&mut buf[filled..]) {
+            Ok(0) => return Ok(false),
+            Ok(read) => filled += read,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use image::{codecs::gif::GifDecoder, AnimationDecoder, GenericImageView};
+
+    use super::{exif, strip_metadata};
+
+    const JPEG: &[u8] = include_bytes!("../../../tests/fixtures/gps.jpg");
+    const PNG: &[u8] = include_bytes!("../../../tests/fixtures/gps.png");
+    const GIF: &[u8] = include_bytes!("../../../tests/fixtures/gps.gif");
+    const WEBP: &[u8] = include_bytes!("../../../tests/fixtures/gps.webp");
+    const ANIMATED_WEBP: &[u8] = include_bytes!("../../../tests/fixtures/gps_animated.webp");
+
+    /// Markers of the EXIF, XMP and comment metadata of the fixtures.
+    const MARKERS: &[&[u8]] = &[b"Eludris Test Camera", b"GPSLatitude", b"secret comment"];
+
+    fn strip(content_type: &str, data: &[u8]) -> Result<Vec<u8>, anyhow::Error> {
+        let mut stripped = vec![];
+        strip_metadata(content_type, &mut Cursor::new(data), &mut stripped)?;
+        Ok(stripped)
+    }
+
+    fn contains(data: &[u8], needle: &[u8]) -> bool {
+        data.windows(needle.len()).any(|window| window == needle)
+    }
+
+    fn count(data: &[u8], needle: &[u8]) -> usize {
+        data.windows(needle.len())
+            .filter(|window| window == &needle)
+            .count()
+    }
+
+    fn assert_stripped(original: &[u8], stripped: &[u8]) {
+        assert!(MARKERS.iter().any(|marker| contains(original, marker)));
+        for marker in MARKERS {
+            assert!(!contains(stripped, marker));
+        }
+    }
+
+    #[test]
+    fn jpeg() {
+        let stripped = strip("image/jpeg", JPEG).unwrap();
+        assert_stripped(JPEG, &stripped);
+        assert!(contains(&stripped, &exif::with_orientation(6)));
+        assert_eq!(
+            image::load_from_memory(&stripped).unwrap().dimensions(),
+            (16, 8)
+        );
+    }
+
+    #[test]
+    fn png() {
+        let stripped = strip("image/png", PNG).unwrap();
+        assert_stripped(PNG, &stripped);
+        assert!(contains(&stripped, &exif::with_orientation(6)));
+        assert_eq!(
+            image::load_from_memory(&stripped).unwrap(),
+            image::load_from_memory(PNG).unwrap()
+        );
+    }
+
+    #[test]
+    fn gif() {
+        let stripped = strip("image/gif", GIF).unwrap();
+        assert_stripped(GIF, &stripped);
+        assert!(contains(&stripped, b"NETSCAPE2.0"));
+        let frames = GifDecoder::new(&stripped[..])
+            .unwrap()
+            .into_frames()
+            .collect_frames()
+            .unwrap();
+        assert_eq!(frames.len(), 2);
+    }
+
+    #[test]
+    fn webp() {
+        let stripped = strip("image/webp", WEBP).unwrap();
+        assert_stripped(WEBP, &stripped);
+        assert!(contains(&stripped, &exif::with_orientation(6)));
+        assert_eq!(
+            image::load_from_memory(&stripped).unwrap(),
+            image::load_from_memory(WEBP).unwrap()
+        );
+    }
+
+    #[test]
+    fn animated_webp() {
+        let stripped = strip("image/webp", ANIMATED_WEBP).unwrap();
+        assert_stripped(ANIMATED_WEBP, &stripped);
+        assert_eq!(count(&stripped, b"ANMF"), 2);
+        assert!(contains(&stripped, b"ANIM"));
+    }
+
+    #[test]
+    fn large_metadata() {
+        // An eXIf chunk too large to be read is dropped along with its orientation.
+        let data = vec![b'x'; exif::MAX_LENGTH as usize + 1];
+        let mut png = PNG[..33].to_vec();
+        png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        png.extend_from_slice(b"eXIf");
+        png.extend_from_slice(&data);
+        png.extend_from_slice(&[0; 4]);
+        png.extend_from_slice(&PNG[33..]);
+        let stripped = strip("image/png", &png).unwrap();
+        assert!(!contains(&stripped, &data[..1024]));
+        assert_eq!(
+            image::load_from_memory(&stripped).unwrap(),
+            image::load_from_memory(PNG).unwrap()
+        );
+    }
+
+    #[test]
+    fn orientation() {
+        assert_eq!(exif::get_orientation(&exif::with_orientation(3)), Some(3));
+        assert_eq!(exif::get_orientation(b"MM\0\x2a"), None);
+    }
+
+    #[test]
+    fn malformed() {
+        assert!(strip("image/jpeg", &JPEG[..JPEG.len() / 4]).is_err());
+        assert!(strip("image/png", &PNG[..PNG.len() / 2]).is_err());
+        assert!(strip("image/gif", &GIF[..GIF.len() / 2]).is_err());
+        assert!(strip("image/webp", b"RIFF\0\0\0\0WEBP").is_err());
+        assert!(strip("text/plain", b"woo").is_err());
+    }
+}
@@ -0,0 +1,95 @@
+use std::io::{self, Read, Seek, Write};
+
+use anyhow::{bail, Context};
+
+use super::{copy_exact, exif, read_full, skip_exact};
+
+/// The signature every PNG starts with.
+const SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// The chunks needed to display an image, including APNG animation chunks.
+const KEPT_CHUNKS: &[&[u8]] = &[
+    b"IHDR", b"PLTE", b"IDAT", b"IEND", b"tRNS", b"gAMA", b"cHRM", b"sRGB", b"iCCP", b"cICP",
+    b"sBIT", b"bKGD", b"pHYs", b"acTL", b"fcTL", b"fdAT",
+];
+
+/// Build a PNG chunk.
+fn chunk(kind: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut chunk = Vec::with_capacity(data.len() + 12);
+    chunk.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    chunk.extend_from_slice(kind);
+    chunk.extend_from_slice(data);
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(kind);
+    hasher.update(data);
+    chunk.extend_from_slice(&hasher.finalize().to_be_bytes());
+    chunk
+}
+
+/// Strip every chunk which is not needed to display the image, such as text and EXIF chunks.
+pub(super) fn strip<R: Read + Seek, W: Write>(
+    input: &mut R,
+    output: &mut W,
+) -> Result<(), anyhow::Error> {
+    let orientation = copy_chunks(input, &mut io::sink(), None)?;
+    input.rewind()?;
+    copy_chunks(
+        input,
+        output,
+        orientation.filter(|&orientation| orientation != 1),
+    )?;
+    Ok(())
+}
+
+/// Copy the chunks needed to display the image to `output`, adding an eXIf chunk holding
+/// `orientation` after the header chunk.
+///
+/// This returns the orientation stored in the eXIf chunk of the input.
+fn copy_chunks<R: Read, W: Write>(
+    input: &mut R,
+    output: &mut W,
+    orientation: Option<u16>,
+) -> Result<Option<u16>, anyhow::Error> {
+    let mut signature = [0; 8];
+    if !read_full(input, &mut signature)? || signature != SIGNATURE {
+        bail!("Missing PNG signature");
+    }
+    output.write_all(SIGNATURE)?;
+
+    let mut found = None;
+    let mut header_copied = false;
+    loop {
+        let mut header = [0; 8];
+        if !read_full(input, &mut header)? {
+            bail!("Missing PNG end chunk");
+        }
+        let length = u32::from_be_bytes(header[..4].try_into()?) as u64;
+        let kind = &header[4..8];
+        if kind == b"eXIf" && length <= exif::MAX_LENGTH {
+            let mut data = vec![0; length as usize];
+            input.read_exact(&mut data).context("Truncated PNG chunk")?;
+            found = found.or_else(|| exif::get_orientation(&data));
+            // The CRC is not needed as the chunk is dropped.
+            skip_exact(input, 4).context("Truncated PNG chunk")?;
+            continue;
+        }
+        if !KEPT_CHUNKS.contains(&kind) {
+            skip_exact(input, length + 4).context("Truncated PNG chunk")?;
+            continue;
+        }
+        if !header_copied && kind != b"IHDR" {
+            bail!("Missing PNG header chunk");
+        }
+        output.write_all(&header)?;
+        copy_exact(input, output, length + 4).context("Truncated PNG chunk")?;
+        if !header_copied {
+            if let Some(orientation) = orientation {
+                output.write_all(&chunk(b"eXIf", &exif::with_orientation(orientation)))?;
+            }
+            header_copied = true;
+        }
+        if kind == b"IEND" {
+            return Ok(found);
+        }
+    }
+}
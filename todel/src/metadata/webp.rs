@@ -0,0 +1,127 @@
+use std::io::{self, Read, Seek, Write};
+
+use anyhow::{bail, Context};
+
+use super::{copy_exact, exif, read_full, skip_exact};
+
+/// The chunks needed to display an image, including animation chunks.
+const KEPT_CHUNKS: &[&[u8]] = &[
+    b"VP8X", b"VP8 ", b"VP8L", b"ALPH", b"ICCP", b"ANIM", b"ANMF",
+];
+/// The VP8X flag set when the image has EXIF metadata.
+const EXIF_FLAG: u8 = 0x08;
+/// The VP8X flag set when the image has XMP metadata.
+const XMP_FLAG: u8 = 0x04;
+
+/// The largest VP8X chunk which is read, the chunk is 10 bytes long as of writing.
+const MAX_VP8X_LENGTH: u64 = 1024;
+
+/// The chunks copied from an image.
+struct CopiedChunks {
+    /// The orientation stored in the EXIF metadata of the input
+    orientation: Option<u16>,
+    /// Whether the image has the extended header, images without it can't have any metadata
+    extended: bool,
+    /// The amount of bytes written
+    length: u64,
+}
+
+/// Write a RIFF chunk, padding it to an even length.
+fn write_chunk<W: Write>(output: &mut W, kind: &[u8], data: &[u8]) -> io::Result<u64> {
+    output.write_all(kind)?;
+    output.write_all(&(data.len() as u32).to_le_bytes())?;
+    output.write_all(data)?;
+    if data.len() % 2 == 1 {
+        output.write_all(&[0])?;
+    }
+    Ok(8 + data.len() as u64 + data.len() as u64 % 2)
+}
+
+/// Strip the EXIF and XMP chunks along with any unknown ones.
+pub(super) fn strip<R: Read + Seek, W: Write>(
+    input: &mut R,
+    output: &mut W,
+) -> Result<(), anyhow::Error> {
+    let chunks = copy_chunks(input, &mut io::sink(), None)?;
+    let exif = chunks
+        .orientation
+        .filter(|&orientation| orientation != 1 && chunks.extended)
+        .map(exif::with_orientation);
+    let exif_length = exif
+        .as_ref()
+        .map_or(0, |exif| 8 + exif.len() as u64 + exif.len() as u64 % 2);
+    input.rewind()?;
+
+    output.write_all(b"RIFF")?;
+    output.write_all(&u32::try_from(4 + chunks.length + exif_length)?.to_le_bytes())?;
+    output.write_all(b"WEBP")?;
+    copy_chunks(input, output, exif.as_deref())?;
+    Ok(())
+}
+
+/// Copy the chunks needed to display the image to `output` without the RIFF header, adding an
+/// EXIF chunk holding the given EXIF data.
+fn copy_chunks<R: Read, W: Write>(
+    input: &mut R,
+    output: &mut W,
+    exif: Option<&[u8]>,
+) -> Result<CopiedChunks, anyhow::Error> {
+    let mut header = [0; 12];
+    if !read_full(input, &mut header)? || &header[..4] != b"RIFF" || &header[8..] != b"WEBP" {
+        bail!("Missing WebP RIFF header");
+    }
+
+    let mut copied = CopiedChunks {
+        orientation: None,
+        extended: false,
+        length: 0,
+    };
+    let mut header = [0; 8];
+    // A truncated chunk header at the end of the image is ignored.
+    while read_full(input, &mut header)? {
+        let kind = &header[..4];
+        let length = u32::from_le_bytes(header[4..].try_into()?) as u64;
+        if copied.length == 0 && KEPT_CHUNKS.contains(&kind) {
+            copied.extended = kind == b"VP8X";
+        }
+        if kind == b"EXIF" && length <= exif::MAX_LENGTH {
+            let mut data = vec![0; length as usize];
+            input
+                .read_exact(&mut data)
+                .context("Truncated WebP chunk")?;
+            copied.orientation = copied.orientation.or_else(|| exif::get_orientation(&data));
+        } else if kind == b"VP8X" && length <= MAX_VP8X_LENGTH {
+            let mut data = vec![0; length as usize];
+            input
+                .read_exact(&mut data)
+                .context("Truncated WebP chunk")?;
+            if let Some(flags) = data.first_mut() {
+                *flags &= !(EXIF_FLAG | XMP_FLAG);
+                if exif.is_some() {
+                    *flags |= EXIF_FLAG;
+                }
+            }
+            copied.length += write_chunk(output, kind, &data)?;
+        } else if KEPT_CHUNKS.contains(&kind) && kind != b"VP8X" {
+            output.write_all(&header)?;
+            copy_exact(input, output, length).context("Truncated WebP chunk")?;
+            if length % 2 == 1 {
+                output.write_all(&[0])?;
+            }
+            copied.length += 8 + length + length % 2;
+        } else {
+            skip_exact(input, length).context("Truncated WebP chunk")?;
+        }
+        // The padding of the last chunk may be missing.
+        io::copy(&mut input.by_ref().take(length % 2), &mut io::sink())?;
+    }
+    if copied.length == 0 {
+        bail!("Missing WebP image data");
+    }
+
+    // Metadata chunks come after the image data.
+    if let Some(exif) = exif {
+        copied.length += write_chunk(output, b"EXIF", exif)?;
+    }
+    Ok(copied)
+}
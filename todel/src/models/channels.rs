@@ -0,0 +1,286 @@
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DisplayFromStr};
+
+/// The channel payload
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Channel {
+    #[serde_as(as = "DisplayFromStr")]
+    pub id: u128,
+    pub name: String,
+    /// The ID of the user who created the channel, channels created before users existed have
+    /// none
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub owner_id: Option<u128>,
+}
+
+/// The data required to create a new channel
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChannelCreate {
+    pub name: String,
+}
+
+/// The data used to edit an existing channel, fields which are not provided are left untouched
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChannelEdit {
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+#[cfg(feature = "logic")]
+mod channel_logic {
+    use sqlx::{pool::PoolConnection, Acquire, MySql};
+    use tokio::sync::Mutex;
+
+    use super::{Channel, ChannelCreate, ChannelEdit};
+    use crate::ids::IDGenerator;
+    use crate::models::{
+        id_from_bytes, ErrorResponse, ErrorResponseData, ForbiddenError, NotFoundError,
+        ServerError, User,
+    };
+
+    impl Channel {
+        /// Store a new channel owned by `owner` and return it.
+        pub async fn create(
+            channel: ChannelCreate,
+            owner: &User,
+            gen: &Mutex<IDGenerator>,
+            db: &mut PoolConnection<MySql>,
+        ) -> Result<Self, ErrorResponse> {
+            let id = gen.lock().await.generate_id().map_err(|e| {
+                log::error!("Failed to generate an id: {}", e);
+                ServerError {
+                    error: "Failed to generate an id".to_string(),
+                }
+                .to_error_response()
+            })?;
+            sqlx::query!(
+                "
+INSERT INTO channels(id, name, owner_id)
+VALUES(?, ?, ?)
+                ",
+                &id.to_be_bytes()[..],
+                channel.name,
+                &owner.id.to_be_bytes()[..],
+            )
+            .execute(&mut *db)
+            .await
+            .map_err(|e| {
+                log::error!("Failed to store channel with id {}: {:?}", id, e);
+                ServerError {
+                    error: "Failed to store channel".to_string(),
+                }
+                .to_error_response()
+            })?;
+
+            Ok(Self {
+                id,
+                name: channel.name,
+                owner_id: Some(owner.id),
+            })
+        }
+
+        /// Get a channel by its id.
+        pub async fn get(id: u128, db: &mut PoolConnection<MySql>) -> Result<Self, ErrorResponse> {
+            sqlx::query!(
+                "
+SELECT name, owner_id
+FROM channels
+WHERE id = ?
+                ",
+                &id.to_be_bytes()[..],
+            )
+            .fetch_optional(&mut *db)
+            .await
+            .map_err(|e| {
+                log::error!("Failed to fetch channel {}: {:?}", id, e);
+                ServerError {
+                    error: "Failed to fetch channel".to_string(),
+                }
+                .to_error_response()
+            })?
+            .ok_or_else(|| NotFoundError.to_error_response())
+            .and_then(|r| {
+                Ok(Self {
+                    id,
+                    name: r.name,
+                    owner_id: r.owner_id.map(id_from_bytes).transpose()?,
+                })
+            })
+        }
+
+        /// Get all the channels of the instance, oldest first.
+        pub async fn get_all(db: &mut PoolConnection<MySql>) -> Result<Vec<Self>, ErrorResponse> {
+            sqlx::query!(
+                "
+SELECT id, name, owner_id
+FROM channels
+ORDER BY id
+                ",
+            )
+            .fetch_all(&mut *db)
+            .await
+            .map_err(|e| {
+                log::error!("Failed to fetch channels: {:?}", e);
+                ServerError {
+                    error: "Failed to fetch channels".to_string(),
+                }
+                .to_error_response()
+            })?
+            .into_iter()
+            .map(|r| {
+                Ok(Self {
+                    id: id_from_bytes(r.id)?,
+                    name: r.name,
+                    owner_id: r.owner_id.map(id_from_bytes).transpose()?,
+                })
+            })
+            .collect()
+        }
+
+        /// Edit an existing channel and return its new state.
+        ///
+        /// Channels can only be edited by their owner.
+        pub async fn edit(
+            id: u128,
+            user_id: u128,
+            edit: ChannelEdit,
+            db: &mut PoolConnection<MySql>,
+        ) -> Result<Self, ErrorResponse> {
+            let mut channel = Self::get(id, db).await?;
+            if channel.owner_id != Some(user_id) {
+                return Err(ForbiddenError.to_error_response());
+            }
+            if let Some(name) = edit.name {
+                channel.name = name;
+            }
+            sqlx::query!(
+                "
+UPDATE channels
+SET name = ?
+WHERE id = ?
+                ",
+                channel.name,
+                &id.to_be_bytes()[..],
+            )
+            .execute(&mut *db)
+            .await
+            .map_err(|e| {
+                log::error!("Failed to edit channel {}: {:?}", id, e);
+                ServerError {
+                    error: "Failed to edit channel".to_string(),
+                }
+                .to_error_response()
+            })?;
+
+            Ok(channel)
+        }
+
+        /// Delete a channel along with all of its messages.
+        ///
+        /// Channels can only be deleted by their owner.
+        pub async fn delete(
+            id: u128,
+            user_id: u128,
+            db: &mut PoolConnection<MySql>,
+        ) -> Result<(), ErrorResponse> {
+            if Self::get(id, db).await?.owner_id != Some(user_id) {
+                return Err(ForbiddenError.to_error_response());
+            }
+            // The channel is only deleted along with all of its messages.
+            let mut transaction = db.begin().await.map_err(|e| {
+                log::error!("Failed to start deleting channel {}: {:?}", id, e);
+                ServerError {
+                    error: "Failed to delete channel".to_string(),
+                }
+                .to_error_response()
+            })?;
+            sqlx::query!(
+                "
+DELETE FROM message_attachments
+WHERE message_id IN (SELECT id FROM messages WHERE channel_id = ?)
+                ",
+                &id.to_be_bytes()[..],
+            )
+            .execute(&mut transaction)
+            .await
+            .map_err(|e| {
+                log::error!("Failed to delete attachments of channel {}: {:?}", id, e);
+                ServerError {
+                    error: "Failed to delete channel".to_string(),
+                }
+                .to_error_response()
+            })?;
+            sqlx::query!(
+                "
+DELETE FROM messages
+WHERE channel_id = ?
+                ",
+                &id.to_be_bytes()[..],
+            )
+            .execute(&mut transaction)
+            .await
+            .map_err(|e| {
+                log::error!("Failed to delete messages of channel {}: {:?}", id, e);
+                ServerError {
+                    error: "Failed to delete channel".to_string(),
+                }
+                .to_error_response()
+            })?;
+            sqlx::query!(
+                "
+DELETE FROM channels
+WHERE id = ?
+                ",
+                &id.to_be_bytes()[..],
+            )
+            .execute(&mut transaction)
+            .await
+            .map_err(|e| {
+                log::error!("Failed to delete channel {}: {:?}", id, e);
+                ServerError {
+                    error: "Failed to delete channel".to_string(),
+                }
+                .to_error_response()
+            })?;
+
+            transaction.commit().await.map_err(|e| {
+                log::error!("Failed to delete channel {}: {:?}", id, e);
+                ServerError {
+                    error: "Failed to delete channel".to_string(),
+                }
+                .to_error_response()
+            })?;
+
+            Ok(())
+        }
+
+        /// Check whether a channel with the provided id exists.
+        pub(crate) async fn exists(
+            id: u128,
+            db: &mut PoolConnection<MySql>,
+        ) -> Result<bool, ErrorResponse> {
+            sqlx::query!(
+                "
+SELECT COUNT(*) AS count
+FROM channels
+WHERE id = ?
+                ",
+                &id.to_be_bytes()[..],
+            )
+            .fetch_one(&mut *db)
+            .await
+            .map(|r| r.count > 0)
+            .map_err(|e| {
+                log::error!("Failed to fetch channel {}: {:?}", id, e);
+                ServerError {
+                    error: "Failed to fetch channel".to_string(),
+                }
+                .to_error_response()
+            })
+        }
+    }
+}
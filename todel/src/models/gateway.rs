@@ -1,13 +1,344 @@
 use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DisplayFromStr};
 
-use super::Message;
+use super::{Channel, InstanceInfo, Message, User};
 
 /// The Pandemonium Payload Enum
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 #[serde(tag = "op", content = "d")]
 pub enum Payload {
     Ping,
     Pong,
+    /// The first payload sent to a client after it connects
+    Hello {
+        /// The interval in milliseconds at which the client is expected to send pings
+        heartbeat_interval: u64,
+        instance_info: InstanceInfo,
+        /// The ID of the client's new session, used to resume it if the connection drops
+        session_id: String,
+    },
+    /// The payload a client sends in response to a `Hello` to authenticate itself
+    Identify {
+        /// A session token obtained from Oprish
+        token: String,
+    },
+    /// The payload sent to a client once it successfully identified
+    Authenticated {
+        user: User,
+        /// The users which are online at the time the client identified, including itself
+        online_users: Vec<User>,
+    },
+    /// The payload a client sends to receive the events it missed since its last connection
+    Resume {
+        session_id: String,
+        /// The sequence number of the last event the client received
+        seq: u64,
+    },
+    /// The payload sent to a client whose session could not be resumed
+    InvalidSession,
+    /// The payload a client sends to only receive the message events of specific channels
+    ///
+    /// Clients receive the message events of every channel until they send this payload.
+    Subscribe {
+        #[serde_as(as = "Vec<DisplayFromStr>")]
+        channel_ids: Vec<u128>,
+    },
+    /// The payload sent to a client which sent an invalid or unsupported payload
+    Error {
+        /// Why the client's payload was rejected
+        message: String,
+    },
     MessageCreate(Message),
+    MessageUpdate(Message),
+    MessageDelete {
+        #[serde_as(as = "DisplayFromStr")]
+        id: u128,
+        #[serde_as(as = "DisplayFromStr")]
+        channel_id: u128,
+    },
+    ChannelCreate(Channel),
+    ChannelUpdate(Channel),
+    ChannelDelete {
+        #[serde_as(as = "DisplayFromStr")]
+        id: u128,
+    },
+    /// The payload sent when a user comes online or goes offline
+    PresenceUpdate {
+        user: User,
+        status: PresenceStatus,
+    },
+    /// The payload sent when a user starts typing in a channel
+    TypingStart {
+        #[serde_as(as = "DisplayFromStr")]
+        channel_id: u128,
+        user: User,
+    },
+}
+
+/// Whether a user has any open gateway connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum PresenceStatus {
+    Online,
+    Offline,
+}
+
+impl Payload {
+    /// The ID of the channel a message or typing event was dispatched in.
+    pub fn channel_id(&self) -> Option<u128> {
+        match self {
+            Self::MessageCreate(message) | Self::MessageUpdate(message) => Some(message.channel_id),
+            Self::MessageDelete { channel_id, .. } | Self::TypingStart { channel_id, .. } => {
+                Some(*channel_id)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// A dispatched event along with its sequence number
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SequencedPayload {
+    pub seq: u64,
+    #[serde(flatten)]
+    pub payload: Payload,
+}
+
+/// The close codes Pandemonium closes connections with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u16)]
+pub enum GatewayCloseCode {
+    /// Something went wrong on the server's side, the client can reconnect right away
+    ServerError = 4000,
+    /// The client sent a frame that could not be decoded
+    InvalidPayload = 4001,
+    /// The client did not send a ping within the heartbeat interval
+    HeartbeatTimeout = 4002,
+    /// The client hit the gateway rate limit and should wait before reconnecting
+    RateLimited = 4003,
+    /// The client could not keep up with the events, it can resume its session
+    Lagged = 4004,
+    /// The server is restarting, the client should reconnect and resume its session
+    Restarting = 4005,
+    /// The client identified with an invalid token
+    AuthenticationFailed = 4006,
+}
+
+impl GatewayCloseCode {
+    /// The human readable reason sent along with the close code.
+    pub fn reason(self) -> &'static str {
+        match self {
+            Self::ServerError => "Server error",
+            Self::InvalidPayload => "Invalid payload",
+            Self::HeartbeatTimeout => "Heartbeat timed out",
+            Self::RateLimited => "Rate limited",
+            Self::Lagged => "Lagged behind",
+            Self::Restarting => "Server restarting",
+            Self::AuthenticationFailed => "Authentication failed",
+        }
+    }
+}
+
+impl From<GatewayCloseCode> for u16 {
+    fn from(code: GatewayCloseCode) -> Self {
+        code as u16
+    }
+}
+
+impl TryFrom<u16> for GatewayCloseCode {
+    type Error = u16;
+
+    fn try_from(code: u16) -> Result<Self, Self::Error> {
+        match code {
+            4000 => Ok(Self::ServerError),
+            4001 => Ok(Self::InvalidPayload),
+            4002 => Ok(Self::HeartbeatTimeout),
+            4003 => Ok(Self::RateLimited),
+            4004 => Ok(Self::Lagged),
+            4005 => Ok(Self::Restarting),
+            4006 => Ok(Self::AuthenticationFailed),
+            code => Err(code),
+        }
+    }
+}
+
+/// The KeyDB channel events are published to.
+#[cfg(feature = "logic")]
+pub const EVENTS_CHANNEL: &str = "oprish-events";
+/// The KeyDB key holding the sequence number of the last published event.
+#[cfg(feature = "logic")]
+pub const EVENT_SEQUENCE_KEY: &str = "events:seq";
+/// The KeyDB key of the sorted set buffering the latest published events.
+#[cfg(feature = "logic")]
+pub const EVENT_BUFFER_KEY: &str = "events:buffer";
+/// The amount of events kept in the buffer for resuming sessions.
+#[cfg(feature = "logic")]
+pub const EVENT_BUFFER_SIZE: u64 = 1000;
+
+#[cfg(feature = "logic")]
+mod gateway_logic {
+    use deadpool_redis::redis::{self, aio::ConnectionLike, AsyncCommands, RedisResult};
+
+    use super::{
+        Payload, SequencedPayload, EVENTS_CHANNEL, EVENT_BUFFER_KEY, EVENT_BUFFER_SIZE,
+        EVENT_SEQUENCE_KEY,
+    };
+
+    /// Assigns the event its sequence number, buffers it and publishes it in one go so that
+    /// events are always published in order.
+    ///
+    /// Payloads always serialize to a JSON object, so the sequence number is prepended to it.
+    const PUBLISH_SCRIPT: &str = r#"
+local seq = redis.call("INCR", KEYS[1])
+local event = '{"seq":' .. seq .. ',' .. string.sub(ARGV[1], 2)
+redis.call("ZADD", KEYS[2], seq, event)
+redis.call("ZREMRANGEBYRANK", KEYS[2], 0, -tonumber(ARGV[2]) - 1)
+redis.call("PUBLISH", ARGV[3], event)
+return seq
+"#;
+
+    impl Payload {
+        /// Publish an event to every gateway client and return its sequence number.
+        pub async fn publish<C>(&self, cache: &mut C) -> RedisResult<u64>
+        where
+            C: ConnectionLike + Send,
+        {
+            let payload = serde_json::to_string(self).map_err(|e| {
+                redis::RedisError::from((
+                    redis::ErrorKind::TypeError,
+                    "Failed to serialize payload",
+                    e.to_string(),
+                ))
+            })?;
+            redis::cmd("EVAL")
+                .arg(PUBLISH_SCRIPT)
+                .arg(2)
+                .arg(EVENT_SEQUENCE_KEY)
+                .arg(EVENT_BUFFER_KEY)
+                .arg(payload)
+                .arg(EVENT_BUFFER_SIZE)
+                .arg(EVENTS_CHANNEL)
+                .query_async(cache)
+                .await
+        }
+    }
+
+    impl SequencedPayload {
+        /// Get the buffered events published after `seq`.
+        ///
+        /// This returns `None` if some of those events are no longer buffered.
+        pub async fn get_after<C>(seq: u64, cache: &mut C) -> RedisResult<Option<Vec<Self>>>
+        where
+            C: ConnectionLike + Send,
+        {
+            let current: Option<u64> = cache.get(EVENT_SEQUENCE_KEY).await?;
+            let current = current.unwrap_or(0);
+            if seq > current {
+                return Ok(None);
+            }
+            let events: Vec<String> = cache
+                .zrangebyscore(EVENT_BUFFER_KEY, format!("({}", seq), "+inf")
+                .await?;
+            let events: Vec<Self> = events
+                .into_iter()
+                .filter_map(|event| match serde_json::from_str(&event) {
+                    Ok(event) => Some(event),
+                    Err(err) => {
+                        log::warn!("Failed to deserialize buffered event: {}", err);
+                        None
+                    }
+                })
+                .collect();
+            if seq < current && events.first().map(|e| e.seq) != Some(seq + 1) {
+                return Ok(None);
+            }
+            Ok(Some(events))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{GatewayCloseCode, Payload, PresenceStatus, SequencedPayload};
+    use crate::models::User;
+
+    #[test]
+    fn close_codes() {
+        for code in 4000..=4006 {
+            assert_eq!(u16::from(GatewayCloseCode::try_from(code).unwrap()), code);
+        }
+        assert_eq!(GatewayCloseCode::try_from(1000), Err(1000));
+    }
+
+    #[test]
+    fn sequenced_payload() {
+        let payload = SequencedPayload {
+            seq: 42,
+            payload: Payload::MessageDelete {
+                id: 1234,
+                channel_id: 1,
+            },
+        };
+        let serialized = serde_json::to_string(&payload).unwrap();
+
+        assert_eq!(
+            serialized,
+            r#"{"seq":42,"op":"MESSAGE_DELETE","d":{"id":"1234","channel_id":"1"}}"#
+        );
+        assert_eq!(
+            serde_json::from_str::<SequencedPayload>(&serialized).unwrap(),
+            payload
+        );
+    }
+
+    #[test]
+    fn presence_update() {
+        let payload = Payload::PresenceUpdate {
+            user: User {
+                id: 1234,
+                username: "woo".to_string(),
+            },
+            status: PresenceStatus::Online,
+        };
+        let serialized = serde_json::to_string(&payload).unwrap();
+
+        assert_eq!(
+            serialized,
+            r#"{"op":"PRESENCE_UPDATE","d":{"user":{"id":"1234","username":"woo"},"status":"ONLINE"}}"#
+        );
+        assert_eq!(
+            serde_json::from_str::<Payload>(&serialized).unwrap(),
+            payload
+        );
+    }
+
+    #[test]
+    fn authenticated() {
+        let user = User {
+            id: 1234,
+            username: "woo".to_string(),
+        };
+        let payload = Payload::Authenticated {
+            user: user.clone(),
+            online_users: vec![
+                User {
+                    id: 42,
+                    username: "wee".to_string(),
+                },
+                user,
+            ],
+        };
+        let serialized = serde_json::to_string(&payload).unwrap();
+
+        assert_eq!(
+            serialized,
+            r#"{"op":"AUTHENTICATED","d":{"user":{"id":"1234","username":"woo"},"online_users":[{"id":"42","username":"wee"},{"id":"1234","username":"woo"}]}}"#
+        );
+        assert_eq!(
+            serde_json::from_str::<Payload>(&serialized).unwrap(),
+            payload
+        );
+    }
 }
@@ -1,8 +1,10 @@
 use serde::{Deserialize, Serialize};
 
+#[cfg(feature = "logic")]
+use crate::Conf;
+
 /// The instance info payload
-#[cfg(not(feature = "logic"))]
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct InstanceInfo {
     pub instance_name: String,
     pub description: Option<String>,
@@ -15,17 +17,20 @@ pub struct InstanceInfo {
     pub attachment_file_size: u64,
 }
 
-/// The instance info payload
 #[cfg(feature = "logic")]
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct InstanceInfo<'a> {
-    pub instance_name: String,
-    pub description: Option<String>,
-    pub version: &'a str,
-    pub message_limit: usize,
-    pub oprish_url: &'a str,
-    pub pandemonium_url: &'a str,
-    pub effis_url: &'a str,
-    pub file_size: u64,
-    pub attachment_file_size: u64,
+impl InstanceInfo {
+    /// Build the instance info of the instance described by the provided config.
+    pub fn from_conf(conf: &Conf) -> Self {
+        Self {
+            instance_name: conf.instance_name.clone(),
+            description: conf.description.clone(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            message_limit: conf.oprish.message_limit,
+            oprish_url: conf.oprish.url.clone(),
+            pandemonium_url: conf.pandemonium.url.clone(),
+            effis_url: conf.effis.url.clone(),
+            file_size: conf.effis.file_size,
+            attachment_file_size: conf.effis.attachment_file_size,
+        }
+    }
 }
@@ -1,8 +1,499 @@
 use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DisplayFromStr};
+
+use super::FileData;
 
 /// The message payload
+#[serde_as]
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Message {
+    /// The message's ID, its creation time can be obtained from its top 64 bits
+    #[serde_as(as = "DisplayFromStr")]
+    pub id: u128,
+    /// The ID of the channel the message was sent in
+    #[serde_as(as = "DisplayFromStr")]
+    pub channel_id: u128,
     pub author: String,
+    /// The ID of the user who sent the message, messages sent before users existed have none
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub author_id: Option<u128>,
     pub content: String,
+    #[serde(default)]
+    pub attachments: Vec<FileData>,
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reply_to: Option<u128>,
+}
+
+/// The data required to create a new message
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MessageCreate {
+    pub content: String,
+    /// The IDs of the Effis attachments this message links to
+    #[serde_as(as = "Vec<DisplayFromStr>")]
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub attachments: Vec<u128>,
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reply_to: Option<u128>,
+}
+
+/// The data used to edit an existing message, fields which are not provided are left untouched
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MessageEdit {
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+}
+
+#[cfg(feature = "logic")]
+mod message_logic {
+    use std::collections::HashMap;
+
+    use sqlx::{pool::PoolConnection, Acquire, FromRow, MySql, Row};
+    use tokio::sync::Mutex;
+
+    use super::{Message, MessageCreate, MessageEdit};
+    use crate::ids::IDGenerator;
+    use crate::models::{
+        id_from_bytes, Channel, ErrorResponse, ErrorResponseData, File, FileData, FileRow,
+        ForbiddenError, NotFoundError, ServerError, User, ValidationError,
+    };
+
+    impl Message {
+        /// Store a new message and return it.
+        ///
+        /// This makes sure that the message's channel, attachments and the message it replies to
+        /// exist beforehand, replies have to be in the same channel as the message they reply to.
+        pub async fn create(
+            channel_id: u128,
+            author: &User,
+            message: MessageCreate,
+            gen: &Mutex<IDGenerator>,
+            db: &mut PoolConnection<MySql>,
+        ) -> Result<Self, ErrorResponse> {
+            if !Channel::exists(channel_id, db).await? {
+                return Err(NotFoundError.to_error_response());
+            }
+            if let Some(reply_to) = message.reply_to {
+                if !Self::exists(channel_id, reply_to, db).await? {
+                    return Err(ValidationError {
+                        field_name: "reply_to".to_string(),
+                        error: format!("Unknown message {}", reply_to),
+                    }
+                    .to_error_response());
+                }
+            }
+            let mut attachments = vec![];
+            for attachment in message.attachments {
+                if attachments.iter().any(|a: &FileData| a.id == attachment) {
+                    continue;
+                }
+                match File::get(attachment, "attachments", db).await? {
+                    Some(file) => attachments.push(file.get_file_data()),
+                    None => {
+                        return Err(ValidationError {
+                            field_name: "attachments".to_string(),
+                            error: format!("Unknown attachment {}", attachment),
+                        }
+                        .to_error_response())
+                    }
+                }
+            }
+
+            let id = gen.lock().await.generate_id().map_err(|e| {
+                log::error!("Failed to generate an id: {}", e);
+                ServerError {
+                    error: "Failed to generate an id".to_string(),
+                }
+                .to_error_response()
+            })?;
+            // The message is only stored once all of its attachments are.
+            let mut transaction = db.begin().await.map_err(|e| {
+                log::error!("Failed to start storing message with id {}: {:?}", id, e);
+                ServerError {
+                    error: "Failed to store message".to_string(),
+                }
+                .to_error_response()
+            })?;
+            sqlx::query!(
+                "
+INSERT INTO messages(id, channel_id, author, author_id, content, reply_to)
+VALUES(?, ?, ?, ?, ?, ?)
+                ",
+                &id.to_be_bytes()[..],
+                &channel_id.to_be_bytes()[..],
+                author.username,
+                &author.id.to_be_bytes()[..],
+                message.content,
+                message.reply_to.map(|id| id.to_be_bytes().to_vec()),
+            )
+            .execute(&mut transaction)
+            .await
+            .map_err(|e| {
+                log::error!("Failed to store message with id {}: {:?}", id, e);
+                ServerError {
+                    error: "Failed to store message".to_string(),
+                }
+                .to_error_response()
+            })?;
+            for (position, attachment) in attachments.iter().enumerate() {
+                sqlx::query!(
+                    "
+INSERT INTO message_attachments(message_id, attachment_id, position)
+VALUES(?, ?, ?)
+                    ",
+                    &id.to_be_bytes()[..],
+                    attachment.id.to_string(),
+                    position as u32,
+                )
+                .execute(&mut transaction)
+                .await
+                .map_err(|e| {
+                    log::error!("Failed to store attachments of message {}: {:?}", id, e);
+                    ServerError {
+                        error: "Failed to store message".to_string(),
+                    }
+                    .to_error_response()
+                })?;
+            }
+            transaction.commit().await.map_err(|e| {
+                log::error!("Failed to store message with id {}: {:?}", id, e);
+                ServerError {
+                    error: "Failed to store message".to_string(),
+                }
+                .to_error_response()
+            })?;
+
+            Ok(Self {
+                id,
+                channel_id,
+                author: author.username.clone(),
+                author_id: Some(author.id),
+                content: message.content,
+                attachments,
+                reply_to: message.reply_to,
+            })
+        }
+
+        /// Get a message by its id and the id of the channel it was sent in.
+        pub async fn get(
+            channel_id: u128,
+            id: u128,
+            db: &mut PoolConnection<MySql>,
+        ) -> Result<Self, ErrorResponse> {
+            let message = sqlx::query!(
+                "
+SELECT author, author_id, content, reply_to
+FROM messages
+WHERE id = ? AND channel_id = ?
+                ",
+                &id.to_be_bytes()[..],
+                &channel_id.to_be_bytes()[..],
+            )
+            .fetch_optional(&mut *db)
+            .await
+            .map_err(|e| {
+                log::error!("Failed to fetch message {}: {:?}", id, e);
+                ServerError {
+                    error: "Failed to fetch message".to_string(),
+                }
+                .to_error_response()
+            })?
+            .ok_or_else(|| NotFoundError.to_error_response())?;
+
+            Ok(Self {
+                id,
+                channel_id,
+                author: message.author,
+                author_id: message.author_id.map(id_from_bytes).transpose()?,
+                content: message.content,
+                attachments: Self::get_attachments(&[id], db)
+                    .await?
+                    .remove(&id)
+                    .unwrap_or_default(),
+                reply_to: message.reply_to.map(id_from_bytes).transpose()?,
+            })
+        }
+
+        /// Edit an existing message and return its new state.
+        ///
+        /// Messages can only be edited by their author.
+        pub async fn edit(
+            channel_id: u128,
+            id: u128,
+            user_id: u128,
+            edit: MessageEdit,
+            db: &mut PoolConnection<MySql>,
+        ) -> Result<Self, ErrorResponse> {
+            let mut message = Self::get(channel_id, id, db).await?;
+            if message.author_id != Some(user_id) {
+                return Err(ForbiddenError.to_error_response());
+            }
+            if let Some(content) = edit.content {
+                message.content = content;
+            }
+            sqlx::query!(
+                "
+UPDATE messages
+SET content = ?
+WHERE id = ?
+                ",
+                message.content,
+                &id.to_be_bytes()[..],
+            )
+            .execute(&mut *db)
+            .await
+            .map_err(|e| {
+                log::error!("Failed to edit message {}: {:?}", id, e);
+                ServerError {
+                    error: "Failed to edit message".to_string(),
+                }
+                .to_error_response()
+            })?;
+
+            Ok(message)
+        }
+
+        /// Delete a message along with its attachment references.
+        ///
+        /// Messages can only be deleted by their author.
+        pub async fn delete(
+            channel_id: u128,
+            id: u128,
+            user_id: u128,
+            db: &mut PoolConnection<MySql>,
+        ) -> Result<(), ErrorResponse> {
+            if Self::get(channel_id, id, db).await?.author_id != Some(user_id) {
+                return Err(ForbiddenError.to_error_response());
+            }
+            // The message is only deleted along with all of its attachment references.
+            let mut transaction = db.begin().await.map_err(|e| {
+                log::error!("Failed to start deleting message {}: {:?}", id, e);
+                ServerError {
+                    error: "Failed to delete message".to_string(),
+                }
+                .to_error_response()
+            })?;
+            sqlx::query!(
+                "
+DELETE FROM messages
+WHERE id = ?
+                ",
+                &id.to_be_bytes()[..],
+            )
+            .execute(&mut transaction)
+            .await
+            .map_err(|e| {
+                log::error!("Failed to delete message {}: {:?}", id, e);
+                ServerError {
+                    error: "Failed to delete message".to_string(),
+                }
+                .to_error_response()
+            })?;
+            sqlx::query!(
+                "
+DELETE FROM message_attachments
+WHERE message_id = ?
+                ",
+                &id.to_be_bytes()[..],
+            )
+            .execute(&mut transaction)
+            .await
+            .map_err(|e| {
+                log::error!("Failed to delete attachments of message {}: {:?}", id, e);
+                ServerError {
+                    error: "Failed to delete message".to_string(),
+                }
+                .to_error_response()
+            })?;
+            transaction.commit().await.map_err(|e| {
+                log::error!("Failed to delete message {}: {:?}", id, e);
+                ServerError {
+                    error: "Failed to delete message".to_string(),
+                }
+                .to_error_response()
+            })?;
+
+            Ok(())
+        }
+
+        /// Check whether a message with the provided id exists in a channel.
+        async fn exists(
+            channel_id: u128,
+            id: u128,
+            db: &mut PoolConnection<MySql>,
+        ) -> Result<bool, ErrorResponse> {
+            sqlx::query!(
+                "
+SELECT COUNT(*) AS count
+FROM messages
+WHERE id = ? AND channel_id = ?
+                ",
+                &id.to_be_bytes()[..],
+                &channel_id.to_be_bytes()[..],
+            )
+            .fetch_one(&mut *db)
+            .await
+            .map(|r| r.count > 0)
+            .map_err(|e| {
+                log::error!("Failed to fetch message {}: {:?}", id, e);
+                ServerError {
+                    error: "Failed to fetch message".to_string(),
+                }
+                .to_error_response()
+            })
+        }
+
+        /// Get the attachments of several messages at once, keyed by the id of their message.
+        async fn get_attachments(
+            ids: &[u128],
+            db: &mut PoolConnection<MySql>,
+        ) -> Result<HashMap<u128, Vec<FileData>>, ErrorResponse> {
+            let mut attachments: HashMap<u128, Vec<FileData>> = HashMap::new();
+            if ids.is_empty() {
+                return Ok(attachments);
+            }
+            let query = format!(
+                "
+SELECT message_attachments.message_id, files.*
+FROM message_attachments
+JOIN files ON files.id = message_attachments.attachment_id
+WHERE message_attachments.message_id IN ({})
+ORDER BY message_attachments.position, files.id
+                ",
+                vec!["?"; ids.len()].join(", ")
+            );
+            let mut query = sqlx::query(&query);
+            for id in ids {
+                query = query.bind(id.to_be_bytes().to_vec());
+            }
+            let rows = query.fetch_all(&mut *db).await.map_err(|e| {
+                log::error!("Failed to fetch attachments of messages: {:?}", e);
+                ServerError {
+                    error: "Failed to fetch messages".to_string(),
+                }
+                .to_error_response()
+            })?;
+            for row in rows {
+                let (message_id, file) = row
+                    .try_get("message_id")
+                    .and_then(|id| Ok((id, FileRow::from_row(&row)?)))
+                    .map_err(|e| {
+                        log::error!("Failed to read attachment of messages: {:?}", e);
+                        ServerError {
+                            error: "Failed to fetch messages".to_string(),
+                        }
+                        .to_error_response()
+                    })?;
+                attachments
+                    .entry(id_from_bytes(message_id)?)
+                    .or_default()
+                    .push(File::try_from(file)?.get_file_data());
+            }
+
+            Ok(attachments)
+        }
+
+        /// Get up to `limit` messages of a channel with an id between `before` and `after`.
+        ///
+        /// The messages are always returned oldest first, if only `after` is provided the
+        /// messages right after it are returned, otherwise the ones right before `before` (or the
+        /// latest ones) are.
+        pub async fn get_history(
+            channel_id: u128,
+            before: Option<u128>,
+            after: Option<u128>,
+            limit: u32,
+            db: &mut PoolConnection<MySql>,
+        ) -> Result<Vec<Self>, ErrorResponse> {
+            if !Channel::exists(channel_id, db).await? {
+                return Err(NotFoundError.to_error_response());
+            }
+            let channel = channel_id.to_be_bytes().to_vec();
+            let before = before.map(|id| id.to_be_bytes().to_vec());
+            let after = after.map(|id| id.to_be_bytes().to_vec());
+            let rows = if before.is_none() && after.is_some() {
+                sqlx::query!(
+                    "
+SELECT id, author, author_id, content, reply_to
+FROM messages
+WHERE channel_id = ?
+AND id > ?
+ORDER BY id ASC
+LIMIT ?
+                    ",
+                    channel,
+                    after,
+                    limit,
+                )
+                .fetch_all(&mut *db)
+                .await
+                .map(|r| {
+                    r.into_iter()
+                        .map(|r| (r.id, r.author, r.author_id, r.content, r.reply_to))
+                        .collect::<Vec<_>>()
+                })
+            } else {
+                sqlx::query!(
+                    "
+SELECT id, author, author_id, content, reply_to
+FROM messages
+WHERE channel_id = ?
+AND (? IS NULL OR id < ?)
+AND (? IS NULL OR id > ?)
+ORDER BY id DESC
+LIMIT ?
+                    ",
+                    channel,
+                    before,
+                    before,
+                    after,
+                    after,
+                    limit,
+                )
+                .fetch_all(&mut *db)
+                .await
+                .map(|r| {
+                    r.into_iter()
+                        .rev()
+                        .map(|r| (r.id, r.author, r.author_id, r.content, r.reply_to))
+                        .collect::<Vec<_>>()
+                })
+            }
+            .map_err(|e| {
+                log::error!("Failed to fetch message history: {:?}", e);
+                ServerError {
+                    error: "Failed to fetch messages".to_string(),
+                }
+                .to_error_response()
+            })?;
+
+            let mut messages = Vec::with_capacity(rows.len());
+            for (id, author, author_id, content, reply_to) in rows {
+                messages.push(Self {
+                    id: id_from_bytes(id)?,
+                    channel_id,
+                    author,
+                    author_id: author_id.map(id_from_bytes).transpose()?,
+                    content,
+                    attachments: vec![],
+                    reply_to: reply_to.map(id_from_bytes).transpose()?,
+                });
+            }
+            let ids: Vec<u128> = messages.iter().map(|m| m.id).collect();
+            let mut attachments = Self::get_attachments(&ids, db).await?;
+            for message in messages.iter_mut() {
+                message.attachments = attachments.remove(&message.id).unwrap_or_default();
+            }
+
+            Ok(messages)
+        }
+    }
 }
@@ -1,15 +1,53 @@
 //! A collection of models and some related function implementations for eludris.
 
+mod channels;
 mod files;
 mod gateway;
 mod info;
 mod messages;
 mod rate_limits;
 mod response;
+mod sessions;
+mod users;
 
+pub use channels::*;
 pub use files::*;
 pub use gateway::*;
 pub use info::*;
 pub use messages::*;
 pub use rate_limits::*;
 pub use response::*;
+pub use sessions::*;
+pub use users::*;
+
+/// Convert an id stored in a `BINARY(16)` column back into an integer.
+///
+/// Malformed ids are logged and turned into a [`ServerError`].
+#[cfg(feature = "logic")]
+pub(crate) fn id_from_bytes(bytes: Vec<u8>) -> Result<u128, ErrorResponse> {
+    bytes
+        .try_into()
+        .map(u128::from_be_bytes)
+        .map_err(|bytes: Vec<u8>| {
+            log::error!("Found a malformed id in the database: {:?}", bytes);
+            ServerError {
+                error: "Found a malformed id".to_string(),
+            }
+            .to_error_response()
+        })
+}
+
+#[cfg(all(test, feature = "logic"))]
+mod tests {
+    use super::id_from_bytes;
+
+    #[test]
+    fn malformed_id() {
+        assert_eq!(
+            id_from_bytes(1234u128.to_be_bytes().to_vec()).unwrap(),
+            1234
+        );
+        assert_eq!(id_from_bytes(vec![0; 8]).unwrap_err().status, 500);
+        assert_eq!(id_from_bytes(vec![]).unwrap_err().status, 500);
+    }
+}
@@ -16,6 +16,8 @@ pub enum ErrorData {
     RateLimitedError(RateLimitError),
     FileSizeRateLimitedError(FileSizeRateLimitedError),
     ValidationError(ValidationError),
+    UnauthorizedError(UnauthorizedError),
+    ForbiddenError(ForbiddenError),
     NotFoundError(NotFoundError),
     ServerError(ServerError),
 }
@@ -41,6 +43,14 @@ pub struct ValidationError {
     pub error: String,
 }
 
+/// The error when the client is missing a valid session token
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UnauthorizedError;
+
+/// The error when the client is not allowed to perform the requested action
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ForbiddenError;
+
 /// The error when the requested resource is not found
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NotFoundError;
@@ -90,6 +100,28 @@ impl ErrorResponseData for ValidationError {
     }
 }
 
+#[cfg(feature = "logic")]
+impl ErrorResponseData for UnauthorizedError {
+    fn to_error_response(self) -> ErrorResponse {
+        ErrorResponse {
+            status: 401,
+            message: "The provided credentials are invalid".to_string(),
+            data: None,
+        }
+    }
+}
+
+#[cfg(feature = "logic")]
+impl ErrorResponseData for ForbiddenError {
+    fn to_error_response(self) -> ErrorResponse {
+        ErrorResponse {
+            status: 403,
+            message: "You are not allowed to perform the requested action".to_string(),
+            data: None,
+        }
+    }
+}
+
 #[cfg(feature = "logic")]
 impl ErrorResponseData for NotFoundError {
     fn to_error_response(self) -> ErrorResponse {
@@ -0,0 +1,231 @@
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DisplayFromStr};
+
+/// The session payload
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Session {
+    #[serde_as(as = "DisplayFromStr")]
+    pub id: u128,
+    /// The ID of the user the session belongs to
+    #[serde_as(as = "DisplayFromStr")]
+    pub user_id: u128,
+}
+
+/// The data required to log into an account
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionCreate {
+    pub username: String,
+    pub password: String,
+}
+
+/// The response to a successful login
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionCreated {
+    /// The token used to authenticate requests, it is only ever sent once
+    pub token: String,
+    pub session: Session,
+}
+
+#[cfg(feature = "logic")]
+mod session_logic {
+    use argon2::{
+        password_hash::{
+            rand_core::{OsRng, RngCore},
+            PasswordHash, PasswordVerifier,
+        },
+        Argon2,
+    };
+    use deadpool_redis::redis::{aio::ConnectionLike, AsyncCommands};
+    use sqlx::{pool::PoolConnection, MySql};
+    use tokio::{sync::Mutex, task};
+
+    use super::{Session, SessionCreate, SessionCreated};
+    use crate::ids::IDGenerator;
+    use crate::models::{
+        id_from_bytes, ErrorResponse, ErrorResponseData, ServerError, UnauthorizedError, User,
+    };
+
+    /// The amount of random bytes in a session token.
+    const TOKEN_LENGTH: usize = 32;
+    /// The number of seconds a validated token is cached in KeyDB for.
+    ///
+    /// Revoked sessions can keep being used for up to this long.
+    const SESSION_CACHE_TTL: usize = 300;
+
+    /// Build the KeyDB key a token's session is cached under.
+    fn cache_key(token: &str) -> String {
+        format!("session_token:{}", sha256::digest(token))
+    }
+
+    impl Session {
+        /// Log into an account and create a new session for it.
+        ///
+        /// Only a hash of the returned token is stored.
+        pub async fn create(
+            session: SessionCreate,
+            gen: &Mutex<IDGenerator>,
+            db: &mut PoolConnection<MySql>,
+        ) -> Result<SessionCreated, ErrorResponse> {
+            let user = sqlx::query!(
+                "
+SELECT id, password
+FROM users
+WHERE username = ?
+                ",
+                session.username,
+            )
+            .fetch_optional(&mut *db)
+            .await
+            .map_err(|e| {
+                log::error!("Failed to fetch user {}: {:?}", session.username, e);
+                ServerError {
+                    error: "Failed to fetch user".to_string(),
+                }
+                .to_error_response()
+            })?
+            .ok_or_else(|| UnauthorizedError.to_error_response())?;
+
+            let user_id = id_from_bytes(user.id)?;
+            let password = session.password;
+            let hash = user.password;
+            let valid = task::spawn_blocking(move || {
+                PasswordHash::new(&hash).map(|hash| {
+                    Argon2::default()
+                        .verify_password(password.as_bytes(), &hash)
+                        .is_ok()
+                })
+            })
+            .await
+            .map_err(|e| e.to_string())
+            .and_then(|valid| valid.map_err(|e| e.to_string()))
+            .map_err(|e| {
+                log::error!("Failed to verify password: {}", e);
+                ServerError {
+                    error: "Failed to create session".to_string(),
+                }
+                .to_error_response()
+            })?;
+            if !valid {
+                return Err(UnauthorizedError.to_error_response());
+            }
+
+            let mut token = [0; TOKEN_LENGTH];
+            OsRng.fill_bytes(&mut token);
+            let token: String = token.iter().map(|b| format!("{:02x}", b)).collect();
+
+            let id = gen.lock().await.generate_id().map_err(|e| {
+                log::error!("Failed to generate an id: {}", e);
+                ServerError {
+                    error: "Failed to generate an id".to_string(),
+                }
+                .to_error_response()
+            })?;
+            sqlx::query!(
+                "
+INSERT INTO sessions(id, user_id, token)
+VALUES(?, ?, ?)
+                ",
+                &id.to_be_bytes()[..],
+                &user_id.to_be_bytes()[..],
+                sha256::digest(token.as_str()),
+            )
+            .execute(&mut *db)
+            .await
+            .map_err(|e| {
+                log::error!("Failed to store session with id {}: {:?}", id, e);
+                ServerError {
+                    error: "Failed to create session".to_string(),
+                }
+                .to_error_response()
+            })?;
+
+            Ok(SessionCreated {
+                token,
+                session: Self { id, user_id },
+            })
+        }
+
+        /// Get the session a token belongs to along with its user.
+        pub async fn validate(
+            token: &str,
+            db: &mut PoolConnection<MySql>,
+        ) -> Result<(Self, User), ErrorResponse> {
+            sqlx::query!(
+                "
+SELECT sessions.id, sessions.user_id, users.username
+FROM sessions
+JOIN users ON users.id = sessions.user_id
+WHERE sessions.token = ?
+                ",
+                sha256::digest(token),
+            )
+            .fetch_optional(&mut *db)
+            .await
+            .map_err(|e| {
+                log::error!("Failed to fetch session: {:?}", e);
+                ServerError {
+                    error: "Failed to fetch session".to_string(),
+                }
+                .to_error_response()
+            })?
+            .map(|r| {
+                let user_id = id_from_bytes(r.user_id)?;
+                Ok((
+                    Self {
+                        id: id_from_bytes(r.id)?,
+                        user_id,
+                    },
+                    User {
+                        id: user_id,
+                        username: r.username,
+                    },
+                ))
+            })
+            .unwrap_or_else(|| Err(UnauthorizedError.to_error_response()))
+        }
+
+        /// Get the cached session a token belongs to along with its user.
+        ///
+        /// Cache failures are logged and treated as cache misses.
+        pub async fn get_cached<C>(token: &str, cache: &mut C) -> Option<(Self, User)>
+        where
+            C: ConnectionLike + Send,
+        {
+            match cache.get::<_, Option<String>>(cache_key(token)).await {
+                Ok(Some(cached)) => match serde_json::from_str(&cached) {
+                    Ok(session) => Some(session),
+                    Err(err) => {
+                        log::warn!("Failed to deserialize cached session: {}", err);
+                        None
+                    }
+                },
+                Ok(None) => None,
+                Err(err) => {
+                    log::warn!("Failed to fetch cached session: {}", err);
+                    None
+                }
+            }
+        }
+
+        /// Cache the session a token belongs to along with its user.
+        pub async fn cache<C>(&self, token: &str, user: &User, cache: &mut C)
+        where
+            C: ConnectionLike + Send,
+        {
+            let cached = match serde_json::to_string(&(self, user)) {
+                Ok(cached) => cached,
+                Err(err) => {
+                    log::warn!("Failed to serialize session {}: {}", self.id, err);
+                    return;
+                }
+            };
+            if let Err(err) = cache
+                .set_ex::<_, _, ()>(cache_key(token), cached, SESSION_CACHE_TTL)
+                .await
+            {
+                log::warn!("Failed to cache session {}: {}", self.id, err);
+            }
+        }
+    }
+}
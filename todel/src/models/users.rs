@@ -0,0 +1,193 @@
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DisplayFromStr};
+
+/// The user payload
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct User {
+    #[serde_as(as = "DisplayFromStr")]
+    pub id: u128,
+    pub username: String,
+}
+
+/// The data required to register a new user
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UserCreate {
+    pub username: String,
+    pub password: String,
+}
+
+#[cfg(feature = "logic")]
+mod user_logic {
+    use argon2::{
+        password_hash::{rand_core::OsRng, PasswordHasher, SaltString},
+        Argon2,
+    };
+    use sqlx::{mysql::MySqlDatabaseError, pool::PoolConnection, MySql, Row};
+    use tokio::{sync::Mutex, task};
+
+    use super::{User, UserCreate};
+    use crate::ids::IDGenerator;
+    use crate::models::{
+        id_from_bytes, ErrorResponse, ErrorResponseData, ServerError, ValidationError,
+    };
+
+    /// MySQL's error number for a duplicate entry in a unique index.
+    const ER_DUP_ENTRY: u16 = 1062;
+
+    fn username_taken() -> ErrorResponse {
+        ValidationError {
+            field_name: "username".to_string(),
+            error: "Username is already taken".to_string(),
+        }
+        .to_error_response()
+    }
+
+    impl User {
+        /// Register a new user and return it.
+        ///
+        /// Only a hash of the user's password is stored.
+        pub async fn create(
+            user: UserCreate,
+            gen: &Mutex<IDGenerator>,
+            db: &mut PoolConnection<MySql>,
+        ) -> Result<Self, ErrorResponse> {
+            if Self::username_exists(&user.username, db).await? {
+                return Err(username_taken());
+            }
+
+            // Hashing is purposefully slow so it's kept off the async runtime.
+            let password = user.password;
+            let hash = task::spawn_blocking(move || {
+                Argon2::default()
+                    .hash_password(password.as_bytes(), &SaltString::generate(&mut OsRng))
+                    .map(|hash| hash.to_string())
+            })
+            .await
+            .map_err(|e| e.to_string())
+            .and_then(|hash| hash.map_err(|e| e.to_string()))
+            .map_err(|e| {
+                log::error!("Failed to hash password: {}", e);
+                ServerError {
+                    error: "Failed to create user".to_string(),
+                }
+                .to_error_response()
+            })?;
+
+            let id = gen.lock().await.generate_id().map_err(|e| {
+                log::error!("Failed to generate an id: {}", e);
+                ServerError {
+                    error: "Failed to generate an id".to_string(),
+                }
+                .to_error_response()
+            })?;
+            sqlx::query!(
+                "
+INSERT INTO users(id, username, password)
+VALUES(?, ?, ?)
+                ",
+                &id.to_be_bytes()[..],
+                user.username,
+                hash,
+            )
+            .execute(&mut *db)
+            .await
+            .map_err(|e| {
+                // A concurrent registration can take the username after the check above.
+                if let sqlx::Error::Database(err) = &e {
+                    if err
+                        .try_downcast_ref::<MySqlDatabaseError>()
+                        .map(|err| err.number())
+                        == Some(ER_DUP_ENTRY)
+                    {
+                        return username_taken();
+                    }
+                }
+                log::error!("Failed to store user with id {}: {:?}", id, e);
+                ServerError {
+                    error: "Failed to create user".to_string(),
+                }
+                .to_error_response()
+            })?;
+
+            Ok(Self {
+                id,
+                username: user.username,
+            })
+        }
+
+        /// Get several users at once by their ids, unknown ids are skipped.
+        pub async fn get_many(
+            ids: &[u128],
+            db: &mut PoolConnection<MySql>,
+        ) -> Result<Vec<Self>, ErrorResponse> {
+            if ids.is_empty() {
+                return Ok(vec![]);
+            }
+            let query = format!(
+                "
+SELECT id, username
+FROM users
+WHERE id IN ({})
+ORDER BY id
+                ",
+                vec!["?"; ids.len()].join(", ")
+            );
+            let mut query = sqlx::query(&query);
+            for id in ids {
+                query = query.bind(id.to_be_bytes().to_vec());
+            }
+            let rows = query.fetch_all(&mut *db).await.map_err(|e| {
+                log::error!("Failed to fetch users: {:?}", e);
+                ServerError {
+                    error: "Failed to fetch users".to_string(),
+                }
+                .to_error_response()
+            })?;
+            let mut users = Vec::with_capacity(rows.len());
+            for row in rows {
+                let (id, username) = row
+                    .try_get("id")
+                    .and_then(|id| Ok((id, row.try_get("username")?)))
+                    .map_err(|e| {
+                        log::error!("Failed to read user: {:?}", e);
+                        ServerError {
+                            error: "Failed to fetch users".to_string(),
+                        }
+                        .to_error_response()
+                    })?;
+                users.push(Self {
+                    id: id_from_bytes(id)?,
+                    username,
+                });
+            }
+
+            Ok(users)
+        }
+
+        /// Check whether a user with the provided username exists.
+        async fn username_exists(
+            username: &str,
+            db: &mut PoolConnection<MySql>,
+        ) -> Result<bool, ErrorResponse> {
+            sqlx::query!(
+                "
+SELECT COUNT(*) AS count
+FROM users
+WHERE username = ?
+                ",
+                username,
+            )
+            .fetch_one(&mut *db)
+            .await
+            .map(|r| r.count > 0)
+            .map_err(|e| {
+                log::error!("Failed to fetch user {}: {:?}", username, e);
+                ServerError {
+                    error: "Failed to fetch user".to_string(),
+                }
+                .to_error_response()
+            })
+        }
+    }
+}
@@ -0,0 +1,237 @@
+use std::{
+    io::{self, SeekFrom},
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use async_trait::async_trait;
+use tokio::{
+    fs,
+    io::{AsyncReadExt, AsyncSeekExt},
+};
+
+use super::{
+    ByteRange, ContentRange, StorageBackend, StoredFile, StoredObject, UnsatisfiableRange,
+};
+
+/// A storage backend keeping files in a directory on the local filesystem.
+#[derive(Debug, Clone)]
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new<T: Into<PathBuf>>(root: T) -> Self {
+        Self { root: root.into() }
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.root.join(key)
+    }
+}
+
+#[async_trait]
+impl StorageBackend for LocalStorage {
+    async fn init(&self) -> io::Result<()> {
+        fs::create_dir_all(&self.root).await
+    }
+
+    async fn put(&self, key: &str, path: &Path) -> io::Result<()> {
+        let dest = self.path(key);
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        if fs::rename(path, &dest).await.is_ok() {
+            return Ok(());
+        }
+        // Renaming fails across filesystems, the file is copied next to its destination first so
+        // that a partially copied file is never served.
+        let mut temp = dest.clone().into_os_string();
+        temp.push(".tmp");
+        if let Err(err) = fs::copy(path, &temp).await {
+            fs::remove_file(&temp).await.ok();
+            return Err(err);
+        }
+        if let Err(err) = fs::rename(&temp, &dest).await {
+            fs::remove_file(&temp).await.ok();
+            return Err(err);
+        }
+        fs::remove_file(path).await
+    }
+
+    async fn get(&self, key: &str, range: Option<ByteRange>) -> io::Result<StoredFile> {
+        let mut file = fs::File::open(self.path(key)).await?;
+        let metadata = file.metadata().await?;
+        let total = metadata.len();
+        let tag = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .map(|modified| format!("{:x}-{:x}", modified.as_nanos(), total));
+        let range = match range {
+            Some(range) => match range.resolve(total) {
+                Some((start, end)) => Some(ContentRange { start, end, total }),
+                None => return Err(UnsatisfiableRange { range, total }.into()),
+            },
+            None => None,
+        };
+        match range {
+            Some(range) => {
+                file.seek(SeekFrom::Start(range.start)).await?;
+                let length = range.end - range.start + 1;
+                Ok(StoredFile {
+                    body: Box::new(file.take(length)),
+                    length,
+                    range: Some(range),
+                    tag,
+                })
+            }
+            None => Ok(StoredFile {
+                body: Box::new(file),
+                length: total,
+                range: None,
+                tag,
+            }),
+        }
+    }
+
+    async fn exists(&self, key: &str) -> io::Result<bool> {
+        match fs::metadata(self.path(key)).await {
+            Ok(_) => Ok(true),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> io::Result<()> {
+        match fs::remove_file(self.path(key)).await {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        }
+    }
+
+    async fn list(&self, prefix: &str) -> io::Result<Vec<StoredObject>> {
+        // Only the directory the prefix points into has to be walked.
+        let start = prefix.rsplit_once('/').map_or("", |(dir, _)| dir);
+        let mut objects = vec![];
+        let mut dirs = vec![start.to_string()];
+        while let Some(dir) = dirs.pop() {
+            let mut entries = match fs::read_dir(self.path(&dir)).await {
+                Ok(entries) => entries,
+                Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+                Err(err) => return Err(err),
+            };
+            while let Some(entry) = entries.next_entry().await? {
+                let name = entry.file_name().to_string_lossy().to_string();
+                let key = match dir.as_str() {
+                    "" => name,
+                    dir => format!("{}/{}", dir, name),
+                };
+                let metadata = entry.metadata().await?;
+                if metadata.is_dir() {
+                    dirs.push(key);
+                } else if key.starts_with(prefix) {
+                    objects.push(StoredObject {
+                        key,
+                        modified: metadata.modified().ok(),
+                    });
+                }
+            }
+        }
+        Ok(objects)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use tokio::{fs, io::AsyncReadExt};
+
+    use super::LocalStorage;
+    use crate::storage::{ByteRange, ContentRange, StorageBackend, UnsatisfiableRange};
+
+    #[tokio::test]
+    async fn round_trip() {
+        let root = env::temp_dir().join(format!("todel-local-storage-{}", std::process::id()));
+        let storage = LocalStorage::new(&root);
+        storage.init().await.unwrap();
+
+        let path = root.join("upload");
+        fs::write(&path, b"woo").await.unwrap();
+        storage.put("attachments/1234", &path).await.unwrap();
+        assert!(fs::metadata(&path).await.is_err());
+
+        assert!(storage.exists("attachments/1234").await.unwrap());
+        let mut file = storage.get("attachments/1234", None).await.unwrap();
+        assert_eq!(file.length, 3);
+        let mut contents = vec![];
+        file.body.read_to_end(&mut contents).await.unwrap();
+        assert_eq!(contents, b"woo");
+
+        let mut file = storage
+            .get("attachments/1234", Some(ByteRange::Last(2)))
+            .await
+            .unwrap();
+        assert_eq!(
+            file.range,
+            Some(ContentRange {
+                start: 1,
+                end: 2,
+                total: 3
+            })
+        );
+        let mut contents = vec![];
+        file.body.read_to_end(&mut contents).await.unwrap();
+        assert_eq!(contents, b"oo");
+        let err = storage
+            .get("attachments/1234", Some(ByteRange::From(3)))
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        assert_eq!(
+            UnsatisfiableRange::from_io_error(&err),
+            Some(UnsatisfiableRange {
+                range: ByteRange::From(3),
+                total: 3
+            })
+        );
+
+        let path = root.join("upload");
+        fs::write(&path, b"wee").await.unwrap();
+        storage
+            .put("attachments/thumbnails/1234_64", &path)
+            .await
+            .unwrap();
+        let mut keys: Vec<String> = storage
+            .list("attachments/")
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|object| object.key)
+            .collect();
+        keys.sort();
+        assert_eq!(
+            keys,
+            vec!["attachments/1234", "attachments/thumbnails/1234_64"]
+        );
+        let thumbnails = storage.list("attachments/thumbnails/1234_").await.unwrap();
+        assert_eq!(thumbnails.len(), 1);
+        assert!(thumbnails[0].modified.is_some());
+        assert!(storage.list("static/").await.unwrap().is_empty());
+
+        storage.delete("attachments/1234").await.unwrap();
+        storage.delete("attachments/1234").await.unwrap();
+        assert!(!storage.exists("attachments/1234").await.unwrap());
+        assert_eq!(
+            storage
+                .get("attachments/1234", None)
+                .await
+                .unwrap_err()
+                .kind(),
+            std::io::ErrorKind::NotFound
+        );
+
+        fs::remove_dir_all(&root).await.unwrap();
+    }
+}
@@ -0,0 +1,224 @@
+//! Storage backends Effis keeps the contents of files in.
+//!
+//! Files are addressed by keys such as `attachments/{file_id}`, which backends map to a path on
+//! disk or an object name.
+
+mod local;
+mod s3;
+
+use std::{error::Error, fmt, io, path::Path, time::SystemTime};
+
+use async_trait::async_trait;
+use tokio::io::AsyncRead;
+
+pub use local::LocalStorage;
+pub use s3::S3Storage;
+
+use crate::conf::StorageConf;
+
+/// The contents of a stored file.
+pub struct StoredFile {
+    pub body: Box<dyn AsyncRead + Send + Unpin>,
+    /// The length of the body in bytes
+    pub length: u64,
+    /// The part of the file the body holds, `None` if it holds the whole file
+    pub range: Option<ContentRange>,
+    /// An identifier of the stored version of the file which changes whenever the file does
+    pub tag: Option<String>,
+}
+
+impl fmt::Debug for StoredFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StoredFile")
+            .field("length", &self.length)
+            .field("range", &self.range)
+            .field("tag", &self.tag)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A file listed by [`StorageBackend::list`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredObject {
+    pub key: String,
+    /// When the file was last modified, if that is known
+    pub modified: Option<SystemTime>,
+}
+
+/// A single range of bytes requested using the HTTP `Range` header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteRange {
+    /// The bytes from the first offset to the second one, inclusive
+    FromTo(u64, u64),
+    /// The bytes from an offset to the end of the file
+    From(u64),
+    /// The last bytes of the file
+    Last(u64),
+}
+
+impl ByteRange {
+    /// Parse the value of a `Range` header.
+    ///
+    /// Requests for several ranges are not supported, like invalid ones they return `None` so
+    /// that the whole file is sent.
+    pub fn parse(header: &str) -> Option<Self> {
+        let (start, end) = header.trim().strip_prefix("bytes=")?.split_once('-')?;
+        let (start, end) = (start.trim(), end.trim());
+        match (start.is_empty(), end.is_empty()) {
+            (false, false) => {
+                let (start, end) = (start.parse().ok()?, end.parse().ok()?);
+                (start <= end).then_some(Self::FromTo(start, end))
+            }
+            (false, true) => start.parse().ok().map(Self::From),
+            (true, false) => end.parse().ok().map(Self::Last),
+            (true, true) => None,
+        }
+    }
+
+    /// Get the first and last offset of the range in a file of a given length, `None` if the
+    /// range is not satisfiable.
+    pub fn resolve(self, length: u64) -> Option<(u64, u64)> {
+        match self {
+            Self::FromTo(start, end) if start < length => Some((start, end.min(length - 1))),
+            Self::From(start) if start < length => Some((start, length - 1)),
+            Self::Last(suffix) if suffix > 0 && length > 0 => {
+                Some((length.saturating_sub(suffix), length - 1))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ByteRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::FromTo(start, end) => write!(f, "bytes={}-{}", start, end),
+            Self::From(start) => write!(f, "bytes={}-", start),
+            Self::Last(suffix) => write!(f, "bytes=-{}", suffix),
+        }
+    }
+}
+
+/// The part of a file a [`StoredFile`] holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContentRange {
+    pub start: u64,
+    /// The last offset of the range, inclusive
+    pub end: u64,
+    /// The length of the whole file
+    pub total: u64,
+}
+
+impl fmt::Display for ContentRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "bytes {}-{}/{}", self.start, self.end, self.total)
+    }
+}
+
+/// The error [`StorageBackend::get`] wraps when the requested range is outside of the file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnsatisfiableRange {
+    pub range: ByteRange,
+    /// The length of the whole file
+    pub total: u64,
+}
+
+impl UnsatisfiableRange {
+    /// Get the unsatisfiable range an error returned by a [`StorageBackend`] was caused by.
+    pub fn from_io_error(err: &io::Error) -> Option<Self> {
+        err.get_ref()?.downcast_ref::<Self>().copied()
+    }
+}
+
+impl fmt::Display for UnsatisfiableRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} is not satisfiable for a file of {} bytes",
+            self.range, self.total
+        )
+    }
+}
+
+impl Error for UnsatisfiableRange {}
+
+impl From<UnsatisfiableRange> for io::Error {
+    fn from(err: UnsatisfiableRange) -> Self {
+        io::Error::new(io::ErrorKind::InvalidInput, err)
+    }
+}
+
+/// A place files can be stored in and fetched from.
+///
+/// Fetching a missing file errors with [`io::ErrorKind::NotFound`] and fetching a range which is
+/// not satisfiable errors with an [`io::ErrorKind::InvalidInput`] wrapping an
+/// [`UnsatisfiableRange`].
+#[async_trait]
+pub trait StorageBackend: Send + Sync {
+    /// Prepare the backend for storing files, creating the directory or bucket they are stored in.
+    async fn init(&self) -> io::Result<()>;
+
+    /// Move the local file at `path` into storage under `key`, replacing any existing file.
+    async fn put(&self, key: &str, path: &Path) -> io::Result<()>;
+
+    /// Fetch the file stored under `key`, or only a range of it.
+    async fn get(&self, key: &str, range: Option<ByteRange>) -> io::Result<StoredFile>;
+
+    /// Check whether a file is stored under `key`.
+    async fn exists(&self, key: &str) -> io::Result<bool>;
+
+    /// Delete the file stored under `key`, deleting a missing file is not an error.
+    async fn delete(&self, key: &str) -> io::Result<()>;
+
+    /// List every file stored under a key starting with `prefix`.
+    async fn list(&self, prefix: &str) -> io::Result<Vec<StoredObject>>;
+}
+
+/// Create the storage backend described by a [`StorageConf`].
+pub fn new_backend(conf: &StorageConf) -> anyhow::Result<Box<dyn StorageBackend>> {
+    Ok(match conf {
+        StorageConf::Local { path } => Box::new(LocalStorage::new(path)),
+        StorageConf::S3 {
+            endpoint,
+            bucket,
+            region,
+            access_key,
+            secret_key,
+        } => Box::new(S3Storage::new(
+            endpoint, bucket, region, access_key, secret_key,
+        )?),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ByteRange;
+
+    #[test]
+    fn parse_range() {
+        assert_eq!(
+            ByteRange::parse("bytes=0-499"),
+            Some(ByteRange::FromTo(0, 499))
+        );
+        assert_eq!(ByteRange::parse("bytes=500-"), Some(ByteRange::From(500)));
+        assert_eq!(ByteRange::parse("bytes=-500"), Some(ByteRange::Last(500)));
+        assert_eq!(ByteRange::parse("bytes=500-499"), None);
+        assert_eq!(ByteRange::parse("bytes=0-1, 5-6"), None);
+        assert_eq!(ByteRange::parse("bytes=-"), None);
+        assert_eq!(ByteRange::parse("items=0-1"), None);
+        assert_eq!(ByteRange::FromTo(0, 499).to_string(), "bytes=0-499");
+    }
+
+    #[test]
+    fn resolve_range() {
+        assert_eq!(ByteRange::FromTo(0, 499).resolve(1000), Some((0, 499)));
+        assert_eq!(ByteRange::FromTo(900, 1500).resolve(1000), Some((900, 999)));
+        assert_eq!(ByteRange::FromTo(1000, 1500).resolve(1000), None);
+        assert_eq!(ByteRange::From(10).resolve(1000), Some((10, 999)));
+        assert_eq!(ByteRange::From(1000).resolve(1000), None);
+        assert_eq!(ByteRange::Last(10).resolve(1000), Some((990, 999)));
+        assert_eq!(ByteRange::Last(2000).resolve(1000), Some((0, 999)));
+        assert_eq!(ByteRange::Last(0).resolve(1000), None);
+        assert_eq!(ByteRange::Last(10).resolve(0), None);
+    }
+}